@@ -0,0 +1,114 @@
+use std::io::IsTerminal;
+use std::process::Stdio;
+
+use anyhow::{bail,Context,Result};
+use async_trait::async_trait;
+
+use tokio::io::{self,AsyncBufReadExt,AsyncReadExt,BufReader,Lines,Stdin};
+use tokio::process::{Child,ChildStdout,Command};
+use tokio::task::JoinHandle;
+
+use tracing::{debug,warn};
+
+use crate::line_queue::{LineSource,SrcString};
+
+/// Reads lines from the stdout of an external command run through the shell,
+/// e.g. `git grep -h fn` or `rg --no-filename impl`.
+#[derive(Debug)]
+pub struct CommandSource {
+    cmd: String,
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+    /// Collects stderr while the command runs, so it can explain a failure.
+    stderr: Option<JoinHandle<String>>,
+    /// Whether any line has been served yet.
+    served: bool,
+}
+
+impl CommandSource {
+    pub fn new(cmd: &str) -> Result<Self> {
+        debug!("Spawning line command: {}", cmd);
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(cmd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to run command `{}`", cmd))?;
+        let stdout = match child.stdout.take() {
+            Some(x) => x,
+            None => bail!("command `{}` has no stdout", cmd),
+        };
+        // read stderr alongside stdout so a chatty command can't fill the pipe and stall
+        let stderr = child.stderr.take().map(|mut stderr| tokio::spawn(async move {
+            let mut text = String::new();
+            let _ = stderr.read_to_string(&mut text).await;
+            text
+        }));
+        Ok(Self {
+            cmd: cmd.to_owned(),
+            child,
+            lines: BufReader::new(stdout).lines(),
+            stderr,
+            served: false,
+        })
+    }
+}
+
+#[async_trait]
+impl LineSource for CommandSource {
+    async fn get_line(&mut self) -> Result<Option<SrcString>> {
+        while let Some(line) = self.lines.next_line().await? {
+            if let Some(src_str) = SrcString::parse(&line, &self.cmd) {
+                self.served = true;
+                return Ok(Some(src_str));
+            }
+        }
+        let status = self.child.wait().await?;
+        debug!("Line command exited with {}", status);
+        let stderr = match self.stderr.take() {
+            Some(task) => task.await.unwrap_or_default(),
+            None => String::new(),
+        };
+        if !status.success() {
+            if !self.served {
+                bail!("command `{}` failed with {}: {}", self.cmd, status, stderr.trim());
+            }
+            // the lines it did print are still worth typing
+            warn!("Line command `{}` exited with {}: {}", self.cmd, status, stderr.trim());
+        }
+        Ok(None)
+    }
+}
+
+/// Reads lines piped into standard input, e.g. `cat snippet.rs | rustic-typster --stdin`.
+/// Key presses are still read from the controlling terminal by crossterm.
+#[derive(Debug)]
+pub struct StdinSource {
+    lines: Lines<BufReader<Stdin>>,
+}
+
+impl StdinSource {
+    pub fn new() -> Result<Self> {
+        if std::io::stdin().is_terminal() {
+            bail!("--stdin expects lines to be piped in");
+        }
+        Ok(Self {
+            lines: BufReader::new(io::stdin()).lines(),
+        })
+    }
+}
+
+#[async_trait]
+impl LineSource for StdinSource {
+    async fn get_line(&mut self) -> Result<Option<SrcString>> {
+        while let Some(line) = self.lines.next_line().await? {
+            if let Some(src_str) = SrcString::parse(&line, "stdin") {
+                return Ok(Some(src_str));
+            }
+        }
+        Ok(None)
+    }
+}
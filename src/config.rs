@@ -0,0 +1,241 @@
+use std::fmt;
+
+use anyhow::{anyhow,bail,Result};
+
+use crate::generator::{Construct,CONSTRUCTS};
+use crate::lessons::Lesson;
+use crate::rng::Rng;
+use crate::rust_src::RUST_SRC_CRATES;
+
+/// Where the practice lines come from.
+#[derive(Debug,Clone)]
+pub enum Source {
+    Crates,
+    Command(String),
+    Stdin,
+    RustSrc(Vec<String>),
+    Lesson(Lesson),
+    Generate(Vec<Construct>),
+    /// The day's challenge, picked from the built-in lessons.
+    Daily,
+}
+
+impl Source {
+    /// Whether the source can move on to a different repository or file,
+    /// the ones whose `LineSource` implements `reroll`.
+    pub fn can_reroll(&self) -> bool {
+        matches!(self, Source::Crates | Source::RustSrc(_))
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Crates => write!(f, "crates.io"),
+            Source::Command(cmd) => write!(f, "command {}", cmd),
+            Source::Stdin => write!(f, "stdin"),
+            Source::RustSrc(crates) => write!(f, "rust-src {}", crates.join(",")),
+            Source::Lesson(lesson) => write!(f, "lesson {}", lesson.title),
+            Source::Generate(constructs) => {
+                let names: Vec<_> = constructs.iter().map(|c| c.name()).collect();
+                write!(f, "generated {}", names.join(","))
+            },
+            Source::Daily => write!(f, "daily challenge"),
+        }
+    }
+}
+
+/// Lengths offered by the timed mode, in seconds.
+pub const TIMED_SECS: [u64; 4] = [15, 30, 60, 120];
+
+/// When a session ends.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Mode {
+    /// Runs until Esc or the source runs out of lines.
+    Endless,
+    /// Ends a fixed number of seconds after the first keystroke.
+    Timed(u64),
+    /// Ends after a number of completed lines.
+    Lines(usize),
+    /// Ends once a number of characters have been typed.
+    Chars(usize),
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mode::Endless => write!(f, "endless"),
+            Mode::Timed(secs) => write!(f, "{}s timed", secs),
+            Mode::Lines(n) => write!(f, "{} lines", n),
+            Mode::Chars(n) => write!(f, "{} chars", n),
+        }
+    }
+}
+
+/// What happens when a wrong character is typed.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ErrorPolicy {
+    /// Errors are shown and can be fixed before pressing Enter.
+    Free,
+    /// Wrong characters are refused.
+    StopOnLetter,
+    /// The next word can't be started until the current one is fixed.
+    StopOnWord,
+    /// The session ends at the first error.
+    SuddenDeath,
+}
+
+impl fmt::Display for ErrorPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorPolicy::Free => write!(f, "free"),
+            ErrorPolicy::StopOnLetter => write!(f, "stop on letter"),
+            ErrorPolicy::StopOnWord => write!(f, "stop on word"),
+            ErrorPolicy::SuddenDeath => write!(f, "sudden death"),
+        }
+    }
+}
+
+#[derive(Debug,Clone)]
+pub struct Config {
+    pub source: Source,
+    pub mode: Mode,
+    pub errors: ErrorPolicy,
+    /// Backspace is ignored and errors stay in the line.
+    pub no_backspace: bool,
+    /// The next line loads as soon as the current one is typed, without Enter.
+    pub auto_advance: bool,
+    /// Closing delimiters are inserted as in an editor and can finish a line,
+    /// and lines are shown auto-indented.
+    pub editor: bool,
+    /// File the keystroke log is written to at the end of the session.
+    pub keylog: Option<String>,
+    /// Race a replay of the best previous attempt at each line.
+    pub ghost: bool,
+    /// Seeds generated and daily lines and the rust-src file order. Defaults
+    /// to the date for the daily challenge and to the time otherwise.
+    pub seed: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            source: Source::Crates,
+            mode: Mode::Endless,
+            errors: ErrorPolicy::Free,
+            no_backspace: false,
+            auto_advance: false,
+            editor: false,
+            keylog: None,
+            ghost: false,
+            seed: 0,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut config = Self::default();
+        let mut seed = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {}", arg));
+            match arg.as_str() {
+                "--cmd" => config.source = Source::Command(value()?.to_owned()),
+                "--stdin" => config.source = Source::Stdin,
+                "--rust-src" => config.source = Source::RustSrc(parse_rust_src(value()?)?),
+                "--lesson" => config.source = Source::Lesson(Lesson::load(value()?)?),
+                "--gen" => config.source = Source::Generate(parse_constructs(value()?)?),
+                "--time" => config.mode = Mode::Timed(parse_timed(value()?)?),
+                "--lines" => config.mode = Mode::Lines(parse_count(value()?)?),
+                "--chars" => config.mode = Mode::Chars(parse_count(value()?)?),
+                "--strict" => config.errors = parse_strict(value()?)?,
+                "--no-backspace" => config.no_backspace = true,
+                "--auto-advance" => config.auto_advance = true,
+                "--editor" => config.editor = true,
+                "--keylog" => config.keylog = Some(value()?.to_owned()),
+                "--ghost" => config.ghost = true,
+                "--daily" => {
+                    // the ghost is your best at today's lines so far
+                    config.source = Source::Daily;
+                    config.ghost = true;
+                },
+                "--seed" => seed = Some(parse_seed(value()?)?),
+                _ => bail!("argument not recognized: {}", arg),
+            }
+        }
+        config.seed = match (seed, &config.source) {
+            (Some(seed), _) => seed,
+            (None, Source::Daily) => Rng::date_seed(),
+            (None, _) => Rng::from_time().next_u64(),
+        };
+        Ok(config)
+    }
+
+    /// Short label of the settings that make sessions comparable.
+    pub fn describe(&self) -> String {
+        let mut label = self.mode.to_string();
+        match self.source {
+            Source::Daily => label = format!("daily challenge {}, {}", self.seed, label),
+            Source::Generate(_) | Source::RustSrc(_) => label += &format!(", seed {}", self.seed),
+            _ => (),
+        }
+        if self.errors != ErrorPolicy::Free {
+            label += &format!(", {}", self.errors);
+        }
+        if self.no_backspace {
+            label += ", no backspace";
+        }
+        if self.auto_advance {
+            label += ", auto advance";
+        }
+        if self.editor {
+            label += ", editor";
+        }
+        label
+    }
+}
+
+fn parse_rust_src(value: &str) -> Result<Vec<String>> {
+    let crates: Vec<String> = value.split(',').map(|s| s.trim().to_owned()).collect();
+    for crat in &crates {
+        if !RUST_SRC_CRATES.contains(&crat.as_str()) {
+            bail!("unknown rust-src crate {}, expected one of {}", crat, RUST_SRC_CRATES.join(","));
+        }
+    }
+    Ok(crates)
+}
+
+fn parse_constructs(value: &str) -> Result<Vec<Construct>> {
+    if value == "all" {
+        return Ok(CONSTRUCTS.to_vec());
+    }
+    value.split(',').map(|s| Construct::from_name(s.trim())).collect()
+}
+
+fn parse_timed(value: &str) -> Result<u64> {
+    match value.parse() {
+        Ok(secs) if TIMED_SECS.contains(&secs) => Ok(secs),
+        _ => bail!("invalid time {}, expected one of {:?} seconds", value, TIMED_SECS),
+    }
+}
+
+fn parse_count(value: &str) -> Result<usize> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => bail!("invalid count {}, expected a positive number", value),
+    }
+}
+
+fn parse_seed(value: &str) -> Result<u64> {
+    value.parse().map_err(|_| anyhow!("invalid seed {}, expected a number", value))
+}
+
+fn parse_strict(value: &str) -> Result<ErrorPolicy> {
+    Ok(match value {
+        "letter" => ErrorPolicy::StopOnLetter,
+        "word" => ErrorPolicy::StopOnWord,
+        "sudden-death" => ErrorPolicy::SuddenDeath,
+        _ => bail!("invalid strict mode {}, expected letter, word or sudden-death", value),
+    })
+}
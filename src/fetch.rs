@@ -7,7 +7,7 @@ use reqwest::{Client,StatusCode};
 use select::{document::Document,predicate::{Predicate,Attr,Name}};
 use tracing::{debug,trace};
 
-use crate::line_queue::SrcString;
+use crate::line_queue::{is_code_line,LineSource,SrcString};
 
 const BASE_CRATES_URL: &str = "https://crates.io/api/v1/crates?sort=recent-downloads";
 static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"),"/",env!("CARGO_PKG_VERSION"));
@@ -23,7 +23,7 @@ impl<T> UrlResource<T> where T: Fetchable {
         match self {
             UrlResource::Resource(_) => {},
             UrlResource::Url(url) => {
-                *self = UrlResource::Resource(T::fetch(&url).await?);
+                *self = UrlResource::Resource(T::fetch(url).await?);
             }
         }
         Ok(())
@@ -54,7 +54,7 @@ impl File {
 impl Fetchable for File {
     async fn fetch(url: &str) -> Result<Self> {
         debug!("Fetching lines from file: {}", url);
-        let contents = get_page_contents(&url).await?;
+        let contents = get_page_contents(url).await?;
         let raw_url = Document::from(contents.as_str())
                 .find(Attr("id","raw-url"))
                 .next()
@@ -63,10 +63,8 @@ impl Fetchable for File {
                 })
                 .unwrap_or(None).expect("");
         let contents = get_page_contents(&raw_url).await?;
-        let lines: VecDeque<_> = contents.split_terminator("\n").map(|s| s.trim()).filter(|s| {
-            s.len() >= 10 && s.len() <= 80
-            &&
-            !s.starts_with("//")
+        let lines: VecDeque<_> = contents.split_terminator("\n").map(|s| s.trim_end()).filter(|s| {
+            is_code_line(s.trim_start())
         }).map(|s| {
             s.to_owned()
         }).collect();
@@ -163,8 +161,8 @@ impl Fetchable for Folder {
                 },
             }
         }
-        let files = file_urls.into_iter().map(|s| UrlResource::Url(s)).collect();
-        let folders = folder_urls.into_iter().map(|s| UrlResource::Url(s)).collect();
+        let files = file_urls.into_iter().map(UrlResource::Url).collect();
+        let folders = folder_urls.into_iter().map(UrlResource::Url).collect();
         Ok(Folder {
             files,
             folders,
@@ -205,6 +203,49 @@ impl Repo {
     }
 }
 
+/// Walks the most recently downloaded crates on crates.io, page by page.
+#[derive(Debug)]
+pub struct CratesIo {
+    repos: VecDeque<Repo>,
+    page_no: u32,
+}
+
+impl Default for CratesIo {
+    fn default() -> Self {
+        Self {
+            repos: VecDeque::new(),
+            page_no: 1,
+        }
+    }
+}
+
+#[async_trait]
+impl LineSource for CratesIo {
+    async fn get_line(&mut self) -> Result<Option<SrcString>> {
+        loop {
+            match self.repos.pop_front() {
+                Some(mut repo) => {
+                    if let Some(line) = repo.get_line().await? {
+                        self.repos.push_front(repo);
+                        return Ok(Some(line));
+                    }
+                },
+                None => {
+                    // fetch more repos
+                    self.repos.extend(get_repo_urls(self.page_no).await?);
+                    self.page_no += 1;
+                },
+            }
+        }
+    }
+
+    fn reroll(&mut self) {
+        if let Some(repo) = self.repos.pop_front() {
+            debug!("Dropping repo: {}", repo.source);
+        }
+    }
+}
+
 async fn get_page_contents(url: &str) -> Result<String> {
     trace!("Fetching url: {}", url);
     let client = Client::builder().user_agent(APP_USER_AGENT).build()?;
@@ -233,7 +274,7 @@ pub async fn get_repo_urls(page_no: u32) -> Result<VecDeque<Repo>> {
             }
         }
     }
-    if results.len() == 0 {
+    if results.is_empty() {
         bail!("No crates found!");
     }
     Ok(results)
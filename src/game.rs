@@ -1,53 +1,170 @@
 use std::convert::TryFrom;
-use std::time::SystemTime;
+use std::fs::File;
+use std::io::{BufWriter,Write};
+use std::time::Duration;
 
-use anyhow::{bail,Result};
+use anyhow::Result;
 
-use crossterm::event::{EventStream,KeyCode,Event,KeyEvent};
+use crossterm::event::{EventStream,KeyCode,Event,KeyEvent,KeyModifiers};
 
 use futures::StreamExt;
 
-use tokio::{select,sync::mpsc::{channel,Receiver}};
+use tokio::{select,sync::mpsc::{channel,Receiver},time::{interval,sleep_until,Instant}};
 
+use crate::config::{Config,ErrorPolicy,Mode};
+use crate::ghost::{Ghost,Ghosts};
+use crate::history::SessionRecord;
 use crate::line_queue::*;
+use crate::race::RaceLink;
+use crate::replay::Recording;
+use crate::stats::{SkipReason,TypingStats};
 use crate::tui::*;
 
-#[derive(Default)]
-pub struct TypingStats {
-    pub total_mistakes: u32,
-    pub total_chars: u32,
-    pub total_time_ms: u32,
+fn ms_since(t: Instant) -> Result<u32> {
+    Ok(u32::try_from(t.elapsed().as_millis())?)
 }
 
-impl TypingStats {
-    pub fn new() -> Self {
-        Self::default()
+/// Resolves at `deadline`, or never if there isn't one.
+pub async fn wait_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => sleep_until(deadline).await,
+        None => futures::future::pending().await,
     }
+}
 
-    pub fn add_line(&mut self, line: &str, time_elapsed_ms: u32) {
-        self.total_chars += line.len() as u32;
-        self.total_time_ms += time_elapsed_ms;
+/// Status row text for sessions of a fixed length.
+fn progress(mode: Mode, stats: &TypingStats, typed: usize) -> Option<String> {
+    match mode {
+        Mode::Lines(n) => Some(format!("line {}/{}", stats.lines.len() + 1, n)),
+        Mode::Chars(n) => Some(format!("{}/{} chars", stats.total_chars as usize + typed, n)),
+        _ => None,
     }
+}
 
-    pub fn add_mistake(&mut self) {
-        self.total_mistakes += 1;
+/// The closing delimiter an editor inserts after typing `c`.
+pub fn auto_closer(c: char) -> Option<char> {
+    match c {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        '"' => Some('"'),
+        _ => None,
     }
 }
 
-async fn get_next_line(rx: &mut Receiver<SrcString>, reader: &mut EventStream) -> Result<Option<SrcString>> {
+/// Windows reports AltGr as Ctrl+Alt, and AltGr types characters such as
+/// `{` and `@` on many layouts.
+const ALT_GR: KeyModifiers = KeyModifiers::CONTROL.union(KeyModifiers::ALT);
+
+/// Whether `key` is Ctrl plus `c`, and not AltGr.
+fn is_ctrl(key: &KeyEvent, c: char) -> bool {
+    key.code == KeyCode::Char(c) && key.modifiers.contains(KeyModifiers::CONTROL) && !key.modifiers.contains(ALT_GR)
+}
+
+/// Esc or Ctrl+C end the session.
+pub fn is_quit(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Esc || is_ctrl(key,'c')
+}
+
+/// Ctrl+Backspace, Alt+Backspace and Ctrl+W delete a word. xterm and VTE
+/// send `^H` for Ctrl+Backspace, which arrives as Ctrl+H unless the terminal
+/// takes the keyboard enhancement flags `setup_tui` pushes.
+fn is_delete_word(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Backspace => key.modifiers.intersects(ALT_GR),
+        _ => is_ctrl(key,'w') || is_ctrl(key,'h'),
+    }
+}
+
+fn is_backspace(key: &KeyEvent) -> bool {
+    key.code == KeyCode::Backspace
+}
+
+/// Ctrl and Alt chords other than the ones handled, which aren't typing.
+/// AltGr is typing.
+fn is_chord(key: &KeyEvent) -> bool {
+    matches!(key.code, KeyCode::Char(_)) && key.modifiers.intersects(ALT_GR) && !key.modifiers.contains(ALT_GR)
+}
+
+/// Number of characters Ctrl+W removes: trailing whitespace, then the word before it.
+fn word_len(typed: &[char]) -> usize {
+    let spaces = typed.iter().rev().take_while(|c| c.is_whitespace()).count();
+    let word = typed.iter().rev().skip(spaces).take_while(|c| !c.is_whitespace()).count();
+    spaces + word
+}
+
+/// Deletes the last `n` typed characters as if backspaced one at a time.
+fn delete_back(n: usize, chars: &[char], typed: &mut Vec<char>, pending: &mut Vec<char>, stats: &mut TypingStats, editor: bool, time_ms: u32) -> Result<()> {
+    for _ in 0..n {
+        let removed = match typed.pop() {
+            Some(x) => x,
+            None => break,
+        };
+        stats.backspace(chars.get(typed.len()).copied(),removed,typed.len(),time_ms);
+        backspace()?;
+        // like an editor, deleting an opener also deletes its empty pair
+        if editor && auto_closer(removed).is_some() && pending.last() == auto_closer(removed).as_ref() {
+            pending.pop();
+        }
+    }
+    if editor {
+        show_pending(&pending.iter().rev().collect::<String>())?;
+    }
+    Ok(())
+}
+
+/// How many of the editor's `pending` closers, nearest first, complete the
+/// line after the typed text, or `None` if it isn't done. Closers the line
+/// doesn't need would go on a later line after Enter. Without backspace
+/// errors can't be fixed, so reaching the end of the line is enough.
+fn line_done(chars: &[char], typed: &[char], pending: &[char], no_backspace: bool) -> Option<usize> {
+    if typed.len() >= chars.len() {
+        return (no_backspace || typed == chars).then_some(0);
+    }
+    if !no_backspace && typed != &chars[..typed.len()] {
+        return None;
+    }
+    let rest = &chars[typed.len()..];
+    let closers: Vec<char> = pending.iter().rev().copied().collect();
+    closers.starts_with(rest).then_some(rest.len())
+}
+
+/// Whether the line is done, dropping the editor's closers. The ones that
+/// complete the line stay out of the typed text, nobody typed them.
+fn finish_line(chars: &[char], typed: &[char], pending: &mut Vec<char>, no_backspace: bool) -> bool {
+    let done = line_done(chars,typed,pending,no_backspace).is_some();
+    if done {
+        pending.clear();
+    }
+    done
+}
+
+/// Whether the next keystroke would leave a word that still has an error in it.
+fn past_bad_word(chars: &[char], typed: &[char]) -> bool {
+    let first_error = match typed.iter().zip(chars).position(|(t, c)| t != c) {
+        Some(i) => i,
+        None if typed.len() > chars.len() => chars.len(),
+        None => return false,
+    };
+    let word_end = chars[first_error..].iter()
+        .position(|c| c.is_whitespace())
+        .map_or(chars.len(), |i| first_error + i);
+    typed.len() >= word_end
+}
+
+async fn get_next_line(rx: &mut Receiver<SrcString>, reader: &mut EventStream, deadline: Option<Instant>) -> Result<Option<SrcString>> {
     loop {
         break select! {
+            _ = wait_until(deadline) => Ok(None),
             src_str_opt = rx.recv() => {
-                match src_str_opt {
-                    Some(x) => Ok(Some(x)),
-                    None => bail!("channel is closed"),
-                }
+                // a closed channel means the source ran out of lines
+                Ok(src_str_opt)
             },
             event = reader.next() => {
                 match event {
                     Some(Ok(event)) => {
                         match event {
-                            Event::Key(KeyEvent {code: KeyCode::Esc, ..}) => {
+                            Event::Key(key) if is_quit(&key) => {
                                 break Ok(None);
                             },
                             _ => continue,
@@ -63,90 +180,296 @@ async fn get_next_line(rx: &mut Receiver<SrcString>, reader: &mut EventStream) -
     }
 }
 
+/// Waits for a key press, returning false if it was a quit key.
+async fn wait_for_key(reader: &mut EventStream) -> Result<bool> {
+    loop {
+        match reader.next().await {
+            Some(Ok(Event::Key(key))) => return Ok(!is_quit(&key)),
+            Some(Ok(_)) => continue,
+            Some(Err(e)) => return Err(e.into()),
+            None => return Ok(false),
+        }
+    }
+}
+
+/// The path of the line just finished, from its key presses in the log.
+fn last_line_ghost(stats: &TypingStats) -> Option<Ghost> {
+    let line = stats.lines.last()?;
+    let shown = stats.shown.len() - 1;
+    let first = stats.events.iter().position(|e| e.line == shown)?;
+    Some(Ghost::from_events(&stats.events[first..],line.time_ms))
+}
+
+/// Writes the keystroke log as one JSON object per line.
+fn write_keylog(path: &str, stats: &TypingStats) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    for event in &stats.events {
+        serde_json::to_writer(&mut file, event)?;
+        writeln!(file)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
 #[tokio::main]
-pub async fn run() -> Result<()>{
+pub async fn run(config: Config) -> Result<()> {
+    play(config, None).await
+}
+
+/// Plays a session, reporting progress to the other racers during a race.
+pub async fn play(config: Config, mut race: Option<RaceLink>) -> Result<()> {
 
     let _guard = setup_tui()?;
 
-    let (tx,mut rx) = channel::<SrcString>(10);
+    let (tx,mut rx) = channel::<SrcString>(QUEUE_LEN);
+    let (reroll_tx,reroll_rx) = channel::<()>(1);
 
-    let line_queue = LineQueue::new(tx.clone());
+    let line_queue = LineQueue::spawn(tx, reroll_rx, config.source.clone(), config.seed);
 
     let cols = show_intro().await?;
     clear_countdown()?;
 
     let mut reader = EventStream::new();
-    let mut start = SystemTime::now();
+    // the session clock for the keystroke log, and the current line's timer
+    let mut clock = Instant::now();
+    let mut start = Instant::now();
 
     let mut need_line = true;
     let mut has_started = false;
 
     let mut src_str: SrcString;
     let mut line: &str = "";
-    let mut source: &str;
+    let mut source: &str = "";
+    let mut rerolled: Option<String> = None;
     let mut typed = Vec::<char>::new();
     let mut chars = Vec::<char>::new();
+    // closers inserted by the editor emulation, nearest to the cursor last
+    let mut pending = Vec::<char>::new();
 
     let mut stats = TypingStats::new();
 
+    // timed sessions start counting down on the first keystroke
+    let mut deadline: Option<Instant> = None;
+    let mut ticker = interval(Duration::from_secs(1));
+    // time spent paused doesn't count towards the line or the countdown
+    let mut paused_at: Option<Instant> = None;
+
+    let mut ghosts = if config.ghost { Some(Ghosts::load()?) } else { None };
+    // the best previous attempt at the current line
+    let mut ghost: Option<Ghost> = None;
+    let mut offset = 0;
+    let mut ghost_ticker = interval(Duration::from_millis(50));
+    let mut race_ticker = interval(Duration::from_millis(250));
+
     loop {
         // fetch next line from queue
         if need_line {
-            src_str = match get_next_line(&mut rx, &mut reader).await? {
+            let mut next = get_next_line(&mut rx, &mut reader, deadline).await?;
+            if let Some(rerolled) = rerolled.take() {
+                // lines queued before the reroll took effect still come from the old source
+                for _ in 0..=QUEUE_LEN {
+                    match &next {
+                        Some(x) if x.source == rerolled => {
+                            next = get_next_line(&mut rx, &mut reader, deadline).await?;
+                        },
+                        _ => break,
+                    }
+                }
+            }
+            src_str = match next {
                 Some(x) => x,
                 None => break,
             };
             need_line = false;
 
-            line = &src_str.string;
+            // indentation isn't typed, the editor mode shows it as auto-indented
+            line = src_str.string.trim_start();
             source = &src_str.source;
+            let indent = if config.editor {
+                indent_width(&src_str.string).min(MAX_LINE_LEN.saturating_sub(line.chars().count()))
+            } else {
+                0
+            };
             
             // show current line with source
-            display_current_line(cols,line,source)?;
+            offset = display_current_line(cols,line,source,indent)?;
+            stats.show_line(line,source,indent,ms_since(clock)?);
+            if let Some(status) = progress(config.mode,&stats,0) {
+                show_status(cols,&status)?;
+            }
             show_cursor()?;
 
             // typing setup
             has_started = false;
             chars = line.chars().collect::<Vec<char>>();
             typed.clear();
+            pending.clear();
+
+            if let Some(ghosts) = &ghosts {
+                ghost = ghosts.get(line).cloned();
+                clear_ghost()?;
+            }
         }
 
-        match reader.next().await {
+        let event = select! {
+            event = reader.next() => event,
+            _ = wait_until(deadline), if paused_at.is_none() => {
+                // partial progress on the last line still counts
+                if has_started {
+                    let elapsed_time_ms = ms_since(start)?;
+                    stats.add_line(line,&typed,elapsed_time_ms);
+                }
+                break;
+            },
+            _ = ticker.tick(), if deadline.is_some() && paused_at.is_none() => {
+                if let Some(deadline) = deadline {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    show_status(cols,&format!("{}s left",left.as_secs_f32().round()))?;
+                }
+                continue;
+            },
+            _ = race_ticker.tick(), if race.is_some() => {
+                if let Some(race) = &race {
+                    let correct = typed.iter().zip(&chars).take_while(|(t, c)| t == c).count();
+                    let line_ms = if has_started { ms_since(start)? } else { 0 };
+                    race.report(stats.total_chars as usize + correct,stats.wpm_with(typed.len() as u32,line_ms),false);
+                    race.show(cols)?;
+                }
+                continue;
+            },
+            _ = ghost_ticker.tick(), if ghost.is_some() && has_started && paused_at.is_none() => {
+                if let Some(ghost) = &ghost {
+                    let player = typed.iter().zip(&chars).take_while(|(t, c)| t == c).count();
+                    show_ghost(cols,offset,&chars,ghost.pos_at(ms_since(start)?),player)?;
+                }
+                continue;
+            },
+        };
+
+        let mut submit = false;
+        match event {
             Some(Ok(event)) => {
                 match event {
-                    Event::Key(KeyEvent {code: KeyCode::Esc, ..}) => {
+                    Event::Key(key) if is_quit(&key) => {
                         break;
                     },
+                    Event::Key(_) if paused_at.is_some() => {
+                        // any other key resumes, shifting the timers past the pause
+                        if let Some(paused_at) = paused_at.take() {
+                            let paused = paused_at.elapsed();
+                            start += paused;
+                            clock += paused;
+                            deadline = deadline.map(|d| d + paused);
+                        }
+                        show_status(cols,&progress(config.mode,&stats,typed.len()).unwrap_or_default())?;
+                        show_cursor()?;
+                    },
+                    Event::Key(key) if is_ctrl(&key,'p') => {
+                        paused_at = Some(Instant::now());
+                        show_paused(cols)?;
+                    },
+                    Event::FocusLost if paused_at.is_none() => {
+                        paused_at = Some(Instant::now());
+                        show_paused(cols)?;
+                    },
                     Event::Key(KeyEvent {code: KeyCode::Enter, ..}) => {
-                        if typed == chars {
-                            let elapsed_time_ms = u32::try_from(start.elapsed()?.as_millis())?;
-                            stats.add_line(line,elapsed_time_ms);
+                        if finish_line(&chars,&typed,&mut pending,config.no_backspace) {
+                            submit = true;
+                        } else if typed.is_empty() && race.is_none() {
+                            stats.skip_line(line,source,SkipReason::Skipped);
                             need_line = true;
-                            show_time(cols,elapsed_time_ms)?;
-                        } else if typed.len() == 0 {
+                        }
+                    },
+                    Event::Key(key) if is_ctrl(&key,'s') => {
+                        if race.is_none() {
+                            stats.skip_line(line,source,SkipReason::Skipped);
                             need_line = true;
+                        } else {
+                            // racers all type the same lines to the finish
+                            show_status(cols,"--- lines can't be skipped in a race ---")?;
                         }
                     },
-                    Event::Key(KeyEvent {code: KeyCode::Backspace, ..}) => {
-                        if typed.len() != 0 {
-                            typed.pop();
-                            backspace()?;
+                    Event::Key(key) if is_ctrl(&key,'r') => {
+                        if config.source.can_reroll() {
+                            stats.skip_line(line,source,SkipReason::Rerolled);
+                            // a full channel means a reroll is already on its way
+                            let _ = reroll_tx.try_send(());
+                            rerolled = Some(source.to_owned());
+                            need_line = true;
+                        } else {
+                            // every line shares the source, dropping them would drop the session
+                            show_status(cols,"--- this source can't reroll, Ctrl+S skips the line ---")?;
                         }
                     },
+                    Event::Key(key) if is_delete_word(&key) && !config.no_backspace => {
+                        delete_back(word_len(&typed),&chars,&mut typed,&mut pending,&mut stats,config.editor,ms_since(clock)?)?;
+                    },
+                    Event::Key(key) if is_ctrl(&key,'u') && !config.no_backspace => {
+                        delete_back(typed.len(),&chars,&mut typed,&mut pending,&mut stats,config.editor,ms_since(clock)?)?;
+                    },
+                    Event::Key(key) if is_backspace(&key) && !config.no_backspace => {
+                        delete_back(1,&chars,&mut typed,&mut pending,&mut stats,config.editor,ms_since(clock)?)?;
+                    },
+                    Event::Key(key) if is_chord(&key) => {
+                        // other chords aren't typing
+                    },
                     Event::Key(KeyEvent {code: KeyCode::Char(x), ..}) => {
                         if !has_started {
                             has_started = true;
-                            start = SystemTime::now();
+                            start = Instant::now();
+                        }
+                        if let (None, Mode::Timed(secs)) = (deadline, config.mode) {
+                            deadline = Some(Instant::now() + Duration::from_secs(secs));
+                            ticker.reset();
+                            show_status(cols,&format!("{}s left",secs))?;
                         }
+                        let expected = chars.get(typed.len()).copied();
+                        let refused = match config.errors {
+                            ErrorPolicy::StopOnLetter => expected != Some(x),
+                            ErrorPolicy::StopOnWord => past_bad_word(&chars,&typed),
+                            _ => false,
+                        };
+                        if refused {
+                            stats.refuse_char(expected,x,typed.len(),ms_since(clock)?);
+                            continue;
+                        }
+                        let correct = stats.type_char(expected,x,typed.len(),ms_since(clock)?);
                         typed.push(x);
+                        type_char(x,correct)?;
 
-                        if typed.len() > chars.len() || chars[typed.len()-1] != x {
-                            type_char(x,false)?;
-                            stats.add_mistake();
-                        } else {
-                            type_char(x,true)?;
+                        if config.editor {
+                            if pending.last() == Some(&x) {
+                                // typing a closer moves over the one the editor inserted
+                                pending.pop();
+                            } else if let Some(closer) = auto_closer(x) {
+                                // no auto-pairs inside a string literal
+                                if !pending.contains(&'"') {
+                                    pending.push(closer);
+                                }
+                            }
+                            show_pending(&pending.iter().rev().collect::<String>())?;
                         }
 
+                        if !correct && config.errors == ErrorPolicy::SuddenDeath {
+                            let elapsed_time_ms = ms_since(start)?;
+                            stats.add_line(line,&typed,elapsed_time_ms);
+                            break;
+                        }
+
+                        if let Mode::Chars(n) = config.mode {
+                            if stats.total_chars as usize + typed.len() >= n {
+                                let elapsed_time_ms = ms_since(start)?;
+                                stats.add_line(line,&typed,elapsed_time_ms);
+                                break;
+                            }
+                            if let Some(status) = progress(config.mode,&stats,typed.len()) {
+                                show_status(cols,&status)?;
+                            }
+                        }
+
+                        if config.auto_advance && finish_line(&chars,&typed,&mut pending,config.no_backspace) {
+                            submit = true;
+                        }
                     },
                     _ => (),
                 }
@@ -156,12 +479,155 @@ pub async fn run() -> Result<()>{
             },
             None => break,
         }
+
+        if submit {
+            let elapsed_time_ms = ms_since(start)?;
+            stats.add_line(line,&typed,elapsed_time_ms);
+            need_line = true;
+            show_line_stats(cols,&stats)?;
+            if let Some(ghost) = ghost.take() {
+                show_ghost_result(cols,elapsed_time_ms,ghost.time_ms)?;
+            }
+            if let Some(ghosts) = &mut ghosts {
+                // only clean attempts become ghosts, closers the editor inserted may end the line
+                if chars.starts_with(&typed) {
+                    if let Some(best) = last_line_ghost(&stats) {
+                        ghosts.record(line,best);
+                    }
+                }
+            }
+            if let Mode::Lines(n) = config.mode {
+                if stats.lines.len() >= n { break; }
+            }
+        }
     }
 
     rx.close();
     line_queue.await??;
 
-    show_results(cols, stats)?;
+    if let (Mode::Timed(secs), Some(deadline)) = (config.mode, deadline) {
+        // the whole test counts, including reading each new line, but not a pause
+        let end = paused_at.unwrap_or_else(Instant::now).min(deadline);
+        let left = deadline.saturating_duration_since(end);
+        stats.session_ms = Some(u32::try_from(Duration::from_secs(secs).saturating_sub(left).as_millis())?);
+    }
+
+    if let Some(race) = &mut race {
+        race.report(stats.total_chars as usize,stats.gross_wpm(),true);
+        show_status(cols,"--- finished, waiting for the other racers (Esc to stop) ---")?;
+        race.wait_for_others(&mut reader,cols).await?;
+    }
+
+    if let Some(path) = &config.keylog {
+        write_keylog(path,&stats)?;
+    }
+    if let Some(ghosts) = &ghosts {
+        ghosts.save()?;
+    }
+
+    let more = show_results(cols, &config.describe(), &stats)?;
+
+    let record = SessionRecord::new(&config,stats);
+    if !record.stats.events.is_empty() {
+        Recording::new(config.describe(),config.editor,&record.stats).save()?;
+        record.append()?;
+    }
+
+    if more && wait_for_key(&mut reader).await? {
+        show_breakdown_page(cols,&record.stats)?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bad_word(line: &str, typed: &str) -> bool {
+        let chars: Vec<char> = line.chars().collect();
+        let typed: Vec<char> = typed.chars().collect();
+        past_bad_word(&chars, &typed)
+    }
+
+    #[test]
+    fn clean_text_is_never_past_a_bad_word() {
+        assert!(!bad_word("let x = 1;", ""));
+        assert!(!bad_word("let x = 1;", "let"));
+        assert!(!bad_word("let x = 1;", "let x = 1;"));
+    }
+
+    #[test]
+    fn a_bad_word_can_be_finished() {
+        assert!(!bad_word("let x = 1;", "lw"));
+    }
+
+    #[test]
+    fn the_space_after_a_bad_word_is_refused() {
+        assert!(bad_word("let x = 1;", "lwt"));
+        assert!(bad_word("let x = 1;", "let y"));
+    }
+
+    #[test]
+    fn a_bad_last_word_blocks_at_the_end_of_the_line() {
+        assert!(!bad_word("x = 1;", "x = 2"));
+        assert!(bad_word("x = 1;", "x = 2;"));
+    }
+
+    #[test]
+    fn extra_characters_count_as_a_bad_word() {
+        assert!(bad_word("x = 1;", "x = 1;;"));
+    }
+
+    #[test]
+    fn alt_gr_characters_are_typing() {
+        let key = |c, modifiers| KeyEvent::new(KeyCode::Char(c), modifiers);
+        assert!(!is_chord(&key('{', ALT_GR)));
+        assert!(!is_chord(&key('@', ALT_GR | KeyModifiers::SHIFT)));
+        assert!(!is_ctrl(&key('c', ALT_GR), 'c'));
+        assert!(is_chord(&key('x', KeyModifiers::CONTROL)));
+        assert!(is_chord(&key('x', KeyModifiers::ALT)));
+    }
+
+    #[test]
+    fn ctrl_backspace_deletes_a_word() {
+        // What crossterm reads from the `^H` xterm and VTE send.
+        let ctrl_h = KeyEvent::new(KeyCode::Char('h'), KeyModifiers::CONTROL);
+        assert!(is_delete_word(&ctrl_h));
+        assert!(!is_backspace(&ctrl_h));
+        // With the keyboard enhancement flags.
+        let ctrl_backspace = KeyEvent::new(KeyCode::Backspace, KeyModifiers::CONTROL);
+        assert!(is_delete_word(&ctrl_backspace));
+        let backspace = KeyEvent::new(KeyCode::Backspace, KeyModifiers::NONE);
+        assert!(is_backspace(&backspace));
+        assert!(!is_delete_word(&backspace));
+    }
+
+    fn done(line: &str, typed: &str, pending: &str, no_backspace: bool) -> Option<usize> {
+        let chars: Vec<char> = line.chars().collect();
+        let typed: Vec<char> = typed.chars().collect();
+        let pending: Vec<char> = pending.chars().collect();
+        line_done(&chars, &typed, &pending, no_backspace)
+    }
+
+    #[test]
+    fn pending_closers_complete_the_line() {
+        assert_eq!(done("f(g(x))", "f(g(x", "))", false), Some(2));
+        assert_eq!(done("f(g(x));", "f(g(x", "))", false), None);
+        assert_eq!(done("f(g(x))", "f(g(y", "))", false), None);
+    }
+
+    #[test]
+    fn closers_the_line_doesnt_need_are_left_out() {
+        // `]` is nearest the cursor, `}` belongs on a later line
+        assert_eq!(done("fn f() { [x]", "fn f() { [x", "}]", false), Some(1));
+        assert_eq!(done("fn main() {", "fn main() {", "}", false), Some(0));
+    }
+
+    #[test]
+    fn without_backspace_reaching_the_end_is_enough() {
+        assert_eq!(done("let x = 1;", "let y = 1;", "", true), Some(0));
+        assert_eq!(done("f(x)", "g(y", ")", true), Some(1));
+        assert_eq!(done("let x = 1;", "let y = 1", "", true), None);
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use anyhow::{bail,Result};
+use async_trait::async_trait;
+
+use crate::line_queue::{LineSource,SrcString,MAX_LINE_LEN};
+use crate::rng::Rng;
+
+/// A piece of Rust syntax the generator can drill.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Construct {
+    Turbofish,
+    Lifetimes,
+    Generics,
+    Closures,
+    Try,
+}
+
+pub const CONSTRUCTS: [Construct; 5] = [
+    Construct::Turbofish,
+    Construct::Lifetimes,
+    Construct::Generics,
+    Construct::Closures,
+    Construct::Try,
+];
+
+impl Construct {
+    pub fn name(self) -> &'static str {
+        match self {
+            Construct::Turbofish => "turbofish",
+            Construct::Lifetimes => "lifetimes",
+            Construct::Generics => "generics",
+            Construct::Closures => "closures",
+            Construct::Try => "try",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        match CONSTRUCTS.iter().find(|c| c.name() == name) {
+            Some(c) => Ok(*c),
+            None => {
+                let names: Vec<_> = CONSTRUCTS.iter().map(|c| c.name()).collect();
+                bail!("unknown construct {}, expected one of {}", name, names.join(","))
+            },
+        }
+    }
+
+    /// Templates use `$` placeholders that are filled in once per line, so
+    /// repeated placeholders (e.g. a lifetime) stay consistent:
+    /// `$a`-`$d` variables, `$f` a function, `$S` a type, `$T` a type
+    /// parameter, `$L`/`$M` lifetimes, `$G`/`$H` nested generic types,
+    /// `$P` a primitive, `$I` an integer type and `$N` a number.
+    fn templates(self) -> &'static [&'static str] {
+        match self {
+            Construct::Turbofish => &[
+                "let $a = $b.iter().cloned().collect::<$G>();",
+                "let $a = \"$N\".parse::<$I>()?;",
+                "let $a = $f::<$G>(&$b);",
+                "let $a = Vec::<$G>::with_capacity($N);",
+                "let $a = std::mem::size_of::<$G>();",
+                "let $a = $b.into_iter().sum::<$I>();",
+                "let $a = $S::<$P>::$f($b, $N);",
+            ],
+            Construct::Lifetimes => &[
+                "fn $f<$L>($a: &$L $S) -> &$L $P {",
+                "struct $S<$L> { $a: &$L str, $b: &$L [$P] }",
+                "impl<$L> $S<$L> {",
+                "fn $f<$L, $M: $L>($a: &$L str, $b: &$M str) -> &$L str {",
+                "let $a: &'static str = \"$b\";",
+                "fn $f(&self) -> impl Iterator<Item = &'_ $P> + '_ {",
+                "fn $f<$L>($a: &$L mut Vec<&$L str>) {",
+                "struct $S<$L, $T: $L> { $a: &$L $T }",
+            ],
+            Construct::Generics => &[
+                "let $a: $G = Default::default();",
+                "fn $f<$T: Clone>($a: $G) -> $H {",
+                "type $S = $G;",
+                "struct $S<$T> { $a: $G, $b: Vec<$T> }",
+                "impl<$T: Debug> From<$G> for $S<$T> {",
+                "fn $f<$T>($a: &[$T]) -> $G where $T: Ord + Copy {",
+            ],
+            Construct::Closures => &[
+                "let $a = $b.iter().map(|$c| $c * $N).collect::<Vec<_>>();",
+                "let $f = |$a: $P| -> $P { $a };",
+                "let $a = $b.iter().filter(|&&$c| $c > $N).count();",
+                "$b.sort_by(|$c, $d| $d.cmp($c));",
+                "let $f = move |$c| $a.contains(&$c);",
+                "let $a: Box<dyn Fn($P) -> $P> = Box::new(|$c| $c);",
+            ],
+            Construct::Try => &[
+                "let $a = $f(&$b)?;",
+                "let $a = $b.get($N).ok_or(Error::Missing)?.$f()?;",
+                "let $a = File::open(&$b)?.metadata()?.len();",
+                "$f($a.as_ref())?.$f($N)?;",
+                "let $a: $I = $b.trim().parse()?;",
+                "return Ok($S::$f($a?, $b)?);",
+            ],
+        }
+    }
+}
+
+const VARS: [&str; 16] = [
+    "acc", "buf", "count", "data", "idx", "items", "key", "line",
+    "map", "name", "node", "out", "path", "res", "total", "value",
+];
+const FNS: [&str; 10] = [
+    "apply", "build", "find", "load", "merge", "parse", "render", "resolve", "split", "visit",
+];
+const TYPES: [&str; 10] = [
+    "Buffer", "Config", "Cursor", "Entry", "Frame", "Graph", "Node", "Parser", "Span", "Token",
+];
+const PARAMS: [&str; 4] = ["T", "U", "K", "V"];
+const LIFETIMES: [&str; 5] = ["'a", "'b", "'src", "'de", "'ctx"];
+const PRIMS: [&str; 8] = ["u8", "u32", "i64", "usize", "f64", "bool", "char", "String"];
+const INTS: [&str; 6] = ["u8", "u16", "u32", "u64", "i32", "usize"];
+
+fn gen_type(rng: &mut Rng, depth: u32) -> String {
+    if depth == 0 || rng.below(3) == 0 {
+        return rng.choose(&PRIMS).to_string();
+    }
+    let inner = gen_type(rng, depth - 1);
+    match rng.below(6) {
+        0 => format!("Vec<{}>", inner),
+        1 => format!("Option<{}>", inner),
+        2 => format!("Box<{}>", inner),
+        3 => format!("Rc<RefCell<{}>>", inner),
+        4 => format!("Result<{}, Error>", inner),
+        _ => format!("HashMap<{}, {}>", rng.choose(&INTS), inner),
+    }
+}
+
+/// Picks a value not yet bound in this line, so e.g. two lifetimes or two
+/// parameters never share a name.
+fn choose_unused(rng: &mut Rng, items: &[&str], bound: &HashMap<char, String>) -> String {
+    loop {
+        let item = rng.choose(items);
+        if !bound.values().any(|v| v == item) {
+            return item.to_string();
+        }
+    }
+}
+
+fn expand(rng: &mut Rng, template: &str) -> String {
+    let mut bound = HashMap::<char, String>::new();
+    let mut out = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        let key = match chars.next() {
+            Some(k) => k,
+            None => break,
+        };
+        if !bound.contains_key(&key) {
+            let value = match key {
+                'a'..='d' => choose_unused(rng, &VARS, &bound),
+                'f' => rng.choose(&FNS).to_string(),
+                'S' => rng.choose(&TYPES).to_string(),
+                'T' => rng.choose(&PARAMS).to_string(),
+                'L' | 'M' => choose_unused(rng, &LIFETIMES, &bound),
+                'G' | 'H' => gen_type(rng, 3),
+                'P' => rng.choose(&PRIMS).to_string(),
+                'I' => rng.choose(&INTS).to_string(),
+                'N' => rng.below(100).to_string(),
+                _ => key.to_string(),
+            };
+            bound.insert(key, value);
+        }
+        out.push_str(&bound[&key]);
+    }
+    out
+}
+
+/// Builds an endless supply of lines from templates for the chosen constructs.
+#[derive(Debug)]
+pub struct Generator {
+    constructs: Vec<Construct>,
+    rng: Rng,
+}
+
+impl Generator {
+    pub fn new(constructs: Vec<Construct>, rng: Rng) -> Self {
+        Self {
+            constructs,
+            rng,
+        }
+    }
+}
+
+#[async_trait]
+impl LineSource for Generator {
+    async fn get_line(&mut self) -> Result<Option<SrcString>> {
+        let construct = *self.rng.choose(&self.constructs);
+        loop {
+            let template = *self.rng.choose(construct.templates());
+            let string = expand(&mut self.rng, template);
+            if string.len() <= MAX_LINE_LEN {
+                return Ok(Some(SrcString {
+                    string,
+                    source: format!("generated {}", construct.name()),
+                }));
+            }
+        }
+    }
+}
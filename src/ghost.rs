@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::{Context,Result};
+use serde::{Deserialize,Serialize};
+use tracing::warn;
+
+use crate::stats::{KeyKind,KeyPress};
+use crate::store::data_dir;
+
+/// The cursor path of a previous attempt at a line.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct Ghost {
+    pub time_ms: u32,
+    /// Time since the first keystroke and the cursor position after each key.
+    pub steps: Vec<(u32, usize)>,
+}
+
+impl Ghost {
+    /// Builds a ghost from the key presses of one line.
+    pub fn from_events(events: &[KeyPress], time_ms: u32) -> Self {
+        let first = events.first().map_or(0, |e| e.time_ms);
+        let steps = events.iter().filter_map(|e| {
+            let pos = match e.kind {
+                KeyKind::Char { .. } => e.pos + 1,
+                KeyKind::Backspace { .. } => e.pos - 1,
+                KeyKind::Refused { .. } => return None,
+            };
+            Some((e.time_ms - first, pos))
+        }).collect();
+        Self {
+            time_ms,
+            steps,
+        }
+    }
+
+    /// Cursor position `elapsed_ms` after the first keystroke.
+    pub fn pos_at(&self, elapsed_ms: u32) -> usize {
+        self.steps.iter().take_while(|(t, _)| *t <= elapsed_ms).last().map_or(0, |(_, pos)| *pos)
+    }
+}
+
+/// Best attempts per line, kept in `ghosts.json` in the data directory.
+#[derive(Debug)]
+pub struct Ghosts {
+    path: PathBuf,
+    ghosts: HashMap<String, Ghost>,
+}
+
+impl Ghosts {
+    pub fn load() -> Result<Self> {
+        let path = data_dir()?.join("ghosts.json");
+        let ghosts = match std::fs::read_to_string(&path) {
+            // a damaged file costs the old ghosts, not the session
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                warn!("Starting without ghosts, failed to parse {}: {}", path.display(), e);
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path,
+            ghosts,
+        })
+    }
+
+    pub fn get(&self, line: &str) -> Option<&Ghost> {
+        self.ghosts.get(line)
+    }
+
+    /// Keeps `ghost` if it beats the stored attempt at `line`.
+    pub fn record(&mut self, line: &str, ghost: Ghost) {
+        if self.get(line).is_none_or(|best| ghost.time_ms < best.time_ms) {
+            self.ghosts.insert(line.to_owned(), ghost);
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        std::fs::write(&self.path, serde_json::to_string(&self.ghosts)?)
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize,Serialize};
+
+use crate::stats::{KeyKind,KeyPress};
+
+/// US keyboard rows, unshifted.
+pub const KEYBOARD: [&str; 4] = [
+    "`1234567890-=",
+    "qwertyuiop[]\\",
+    "asdfghjkl;'",
+    "zxcvbnm,./",
+];
+
+const SHIFTED: &str = "~!@#$%^&*()_+{}|:\"<>?";
+const UNSHIFTED: &str = "`1234567890-=[]\\;',./";
+
+/// Heat levels used for colouring, from cool to hot.
+pub const LEVELS: usize = 5;
+
+/// The key a character is typed on, ignoring shift.
+pub fn base_key(c: char) -> char {
+    match SHIFTED.chars().position(|s| s == c) {
+        Some(i) => UNSHIFTED.chars().nth(i).unwrap_or(c),
+        None => c.to_ascii_lowercase(),
+    }
+}
+
+/// Counts for one key or bigram.
+#[derive(Debug,Clone,Default,Serialize,Deserialize)]
+pub struct KeyStat {
+    pub count: u32,
+    pub errors: u32,
+    /// Keystrokes with a previous key on the same line to time from.
+    pub timed: u32,
+    pub total_ms: u32,
+}
+
+impl KeyStat {
+    pub fn add(&mut self, correct: bool, latency_ms: Option<u32>) {
+        self.count += 1;
+        if !correct {
+            self.errors += 1;
+        }
+        if let Some(ms) = latency_ms {
+            self.timed += 1;
+            self.total_ms += ms;
+        }
+    }
+
+    pub fn latency_ms(&self) -> Option<u32> {
+        self.total_ms.checked_div(self.timed)
+    }
+
+    pub fn error_rate(&self) -> f32 {
+        self.errors as f32 / self.count.max(1) as f32
+    }
+}
+
+/// Latency and errors per key and per bigram of the expected text.
+#[derive(Debug,Default)]
+pub struct Heatmap {
+    pub keys: HashMap<char, KeyStat>,
+    pub bigrams: HashMap<String, KeyStat>,
+}
+
+impl Heatmap {
+    pub fn new(events: &[KeyPress]) -> Self {
+        let mut heatmap = Self::default();
+        let mut prev: Option<&KeyPress> = None;
+        for event in events {
+            let (expected, correct) = match event.kind {
+                KeyKind::Char { expected: Some(c), correct, .. } => (c, correct),
+                KeyKind::Refused { expected: Some(c), .. } => (c, false),
+                _ => {
+                    prev = Some(event);
+                    continue;
+                },
+            };
+            let same_line = prev.filter(|p| p.line == event.line);
+            let latency_ms = same_line.map(|p| event.time_ms - p.time_ms);
+            heatmap.keys.entry(base_key(expected)).or_default().add(correct, latency_ms);
+            // a bigram is two expected characters typed one after the other
+            if let Some(KeyPress { kind: KeyKind::Char { expected: Some(p), correct: true, .. }, pos, .. }) = same_line {
+                if pos + 1 == event.pos {
+                    let bigram: String = [*p, expected].iter().collect();
+                    heatmap.bigrams.entry(bigram).or_default().add(correct, latency_ms);
+                }
+            }
+            prev = Some(event);
+        }
+        heatmap
+    }
+
+    fn median_latency_ms(&self) -> u32 {
+        let mut latencies: Vec<_> = self.keys.values().filter_map(|k| k.latency_ms()).collect();
+        latencies.sort_unstable();
+        latencies.get(latencies.len() / 2).copied().unwrap_or(0)
+    }
+
+    /// How slow `key` is compared to the median key, from 0 to `LEVELS - 1`.
+    pub fn latency_level(&self, key: char) -> Option<usize> {
+        let ms = self.keys.get(&key)?.latency_ms()?;
+        let ratio = ms as f32 / self.median_latency_ms().max(1) as f32;
+        Some([0.8, 0.95, 1.1, 1.3].iter().take_while(|&&r| ratio >= r).count())
+    }
+
+    /// How error-prone `key` is, from 0 to `LEVELS - 1`.
+    pub fn error_level(&self, key: char) -> Option<usize> {
+        let rate = self.keys.get(&key)?.error_rate();
+        Some([f32::MIN_POSITIVE, 0.02, 0.05, 0.1].iter().take_while(|&&r| rate >= r).count())
+    }
+
+    /// Timed bigrams, slowest first.
+    pub fn slowest_bigrams(&self, n: usize) -> Vec<(&str, &KeyStat)> {
+        let mut bigrams: Vec<_> = self.bigrams.iter()
+            .filter(|(_, s)| s.timed > 0)
+            .map(|(b, s)| (b.as_str(), s))
+            .collect();
+        bigrams.sort_by_key(|(b, s)| (std::cmp::Reverse(s.latency_ms()), *b));
+        bigrams.truncate(n);
+        bigrams
+    }
+
+    /// Bigrams with errors, highest error rate first.
+    pub fn error_bigrams(&self, n: usize) -> Vec<(&str, &KeyStat)> {
+        let mut bigrams: Vec<_> = self.bigrams.iter()
+            .filter(|(_, s)| s.errors > 0)
+            .map(|(b, s)| (b.as_str(), s))
+            .collect();
+        bigrams.sort_by(|a, b| b.1.error_rate().total_cmp(&a.1.error_rate()).then(b.1.count.cmp(&a.1.count)).then(a.0.cmp(b.0)));
+        bigrams.truncate(n);
+        bigrams
+    }
+}
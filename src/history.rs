@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind,Read,Seek,SeekFrom,Write};
+use std::path::PathBuf;
+
+use anyhow::{anyhow,bail,Context,Result};
+use serde::{Deserialize,Serialize};
+
+use crate::config::Config;
+use crate::heatmap::KeyStat;
+use crate::stats::TypingStats;
+use crate::store::{data_dir,unix_secs,utc_date};
+use crate::tokens::{token_stats,Category};
+
+fn history_path() -> Result<PathBuf> {
+    Ok(data_dir()?.join("history.jsonl"))
+}
+
+/// One finished session, as kept in `history.jsonl`.
+#[derive(Serialize,Deserialize)]
+pub struct SessionRecord {
+    /// Unix time the session ended.
+    pub time: u64,
+    /// UTC date the session ended, as `YYYY-MM-DD`.
+    pub date: String,
+    pub mode: String,
+    /// The settings label shown with the results.
+    pub settings: String,
+    pub source: String,
+    pub seed: u64,
+    pub gross_wpm: f32,
+    pub net_wpm: f32,
+    pub accuracy: f32,
+    pub consistency: Option<f32>,
+    pub burst_wpm: Option<f32>,
+    pub reaction_ms: Option<u32>,
+    pub hesitations: usize,
+    pub tokens: BTreeMap<Category, KeyStat>,
+    /// Per-line results and the lines shown with their sources.
+    pub stats: TypingStats,
+}
+
+impl SessionRecord {
+    pub fn new(config: &Config, stats: TypingStats) -> Self {
+        let time = unix_secs();
+        let (year, month, day) = utc_date(time);
+        Self {
+            time,
+            date: format!("{:04}-{:02}-{:02}", year, month, day),
+            mode: config.mode.to_string(),
+            settings: config.describe(),
+            source: config.source.to_string(),
+            seed: config.seed,
+            gross_wpm: stats.gross_wpm(),
+            net_wpm: stats.net_wpm(),
+            accuracy: stats.accuracy(),
+            consistency: stats.consistency(),
+            burst_wpm: stats.burst_wpm(),
+            reaction_ms: stats.reaction_ms(),
+            hesitations: stats.hesitations(),
+            tokens: token_stats(&stats).into_iter().collect(),
+            stats,
+        }
+    }
+
+    /// Appends the record to the history as one JSON line.
+    pub fn append(&self) -> Result<()> {
+        let path = history_path()?;
+        let mut file = OpenOptions::new().create(true).read(true).append(true).open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        let mut json = String::new();
+        // a session cut short mid-write mustn't swallow this one too
+        if file.metadata()?.len() > 0 {
+            let mut last = [0];
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+            if last[0] != b'\n' {
+                json.push('\n');
+            }
+        }
+        json += &serde_json::to_string(self)?;
+        json.push('\n');
+        file.write_all(json.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Every session in the history, oldest first. Lines that don't parse, such
+/// as one cut short by a crash, are skipped with a note on stderr.
+pub fn load() -> Result<Vec<SessionRecord>> {
+    let path = history_path()?;
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        match serde_json::from_str(line) {
+            Ok(record) => records.push(record),
+            Err(e) => eprintln!("Skipping {}:{}, not a session record: {}", path.display(), i + 1, e),
+        }
+    }
+    Ok(records)
+}
+
+/// Days shown in the charts when `--days` isn't given.
+pub const DEFAULT_DAYS: u64 = 30;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+#[derive(Debug)]
+pub struct Stats {
+    pub days: u64,
+}
+
+impl Stats {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut days = DEFAULT_DAYS;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {}", arg));
+            match arg.as_str() {
+                "--days" => days = match value()?.parse() {
+                    Ok(n) if n > 0 => n,
+                    _ => bail!("invalid number of days, expected a positive number"),
+                },
+                _ => bail!("argument not recognized: {}", arg),
+            }
+        }
+        Ok(Self {
+            days,
+        })
+    }
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> Option<f32> {
+    let (sum, n) = values.fold((0.0, 0), |(sum, n), v| (sum + v, n + 1));
+    if n == 0 { None } else { Some(sum / n as f32) }
+}
+
+fn format_duration(ms: u64) -> String {
+    let mins = ms / 60_000;
+    if mins >= 60 {
+        format!("{}h {}m", mins / 60, mins % 60)
+    } else {
+        format!("{}m {}s", mins, ms / 1000 % 60)
+    }
+}
+
+/// One bar per value scaled between `min` and `max`, blank for days without practice.
+fn sparkline(values: &[Option<f32>], min: f32, max: f32) -> String {
+    values.iter().map(|v| match v {
+        Some(v) if max > min => SPARKS[(((v - min) / (max - min)) * (SPARKS.len() - 1) as f32).round() as usize],
+        Some(_) => SPARKS[SPARKS.len() / 2],
+        None => ' ',
+    }).collect()
+}
+
+fn print_chart(label: &str, unit: &str, values: &[Option<f32>]) {
+    let present = values.iter().flatten().copied();
+    let min = present.clone().fold(f32::INFINITY, f32::min);
+    let max = present.fold(f32::NEG_INFINITY, f32::max);
+    if min > max {
+        println!("  {:<10} no sessions", label);
+    } else {
+        println!("  {:<10} {}  {:.0}-{:.0}{}", label, sparkline(values, min, max), min, max, unit);
+    }
+}
+
+fn date(day: u64) -> String {
+    let (year, month, day) = utc_date(day * 86_400);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Prints personal bests, averages and daily trends from the history.
+pub fn stats(args: Stats) -> Result<()> {
+    let records = load()?;
+    if records.is_empty() {
+        println!("No sessions yet, they are added to {} as you play.", history_path()?.display());
+        return Ok(());
+    }
+
+    let total_ms: u64 = records.iter().map(|r| r.stats.total_time_ms as u64).sum();
+    let mut days: Vec<u64> = records.iter().map(|r| r.time / 86_400).collect();
+    days.dedup();
+    println!("{} sessions on {} days, {} of typing", records.len(), days.len(), format_duration(total_ms));
+
+    println!();
+    println!("Personal bests");
+    // sessions only compare under the same mode and rules
+    let mut settings: Vec<&str> = records.iter().map(|r| r.settings.as_str()).collect();
+    settings.sort_unstable();
+    settings.dedup();
+    for label in settings {
+        let best = records.iter()
+            .filter(|r| r.settings == label)
+            .max_by(|a, b| a.net_wpm.total_cmp(&b.net_wpm));
+        if let Some(best) = best {
+            println!("  {:>5.1} wpm net  {:>5.1}% accuracy  {}  {}", best.net_wpm, best.accuracy, best.date, label);
+        }
+    }
+
+    println!();
+    println!("Averages      wpm net  accuracy  sessions");
+    let today = unix_secs() / 86_400;
+    for (label, since) in [("last 7 days", today.saturating_sub(6)), ("last 30 days", today.saturating_sub(29)), ("all time", 0)] {
+        let recent: Vec<_> = records.iter().filter(|r| r.time / 86_400 >= since).collect();
+        if let (Some(wpm), Some(accuracy)) = (mean(recent.iter().map(|r| r.net_wpm)), mean(recent.iter().map(|r| r.accuracy))) {
+            println!("  {:<12} {:>6.1}  {:>7.1}%  {:>8}", label, wpm, accuracy, recent.len());
+        }
+    }
+
+    println!();
+    let first = today.saturating_sub(args.days - 1);
+    let per_day = |value: fn(&SessionRecord) -> f32| -> Vec<Option<f32>> {
+        (first..=today).map(|day| mean(records.iter().filter(|r| r.time / 86_400 == day).map(value))).collect()
+    };
+    println!("By day, {} to {}", date(first), date(today));
+    print_chart("wpm net", " wpm", &per_day(|r| r.net_wpm));
+    print_chart("accuracy", "%", &per_day(|r| r.accuracy));
+
+    let mut tokens = BTreeMap::<Category, KeyStat>::new();
+    for record in &records {
+        for (cat, stat) in &record.tokens {
+            let total = tokens.entry(*cat).or_default();
+            total.count += stat.count;
+            total.errors += stat.errors;
+            total.timed += stat.timed;
+            total.total_ms += stat.total_ms;
+        }
+    }
+    if !tokens.is_empty() {
+        let mut tokens: Vec<_> = tokens.into_iter().collect();
+        tokens.sort_by_key(|(_, s)| std::cmp::Reverse(s.latency_ms()));
+        println!();
+        println!("By token, slowest first");
+        for (cat, stat) in tokens {
+            println!("  {:<14} {:>4}ms per key  {:>4.1}% errors", cat.name(), stat.latency_ms().unwrap_or(0), stat.error_rate() * 100.0);
+        }
+    }
+    Ok(())
+}
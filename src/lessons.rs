@@ -0,0 +1,128 @@
+use std::collections::VecDeque;
+
+use anyhow::{bail,Context,Result};
+use async_trait::async_trait;
+
+use tracing::debug;
+
+use crate::line_queue::{LineSource,SrcString,MAX_LINE_LEN};
+use crate::rng::Rng;
+
+/// Number of lines in a daily challenge.
+pub const DAILY_LINES: usize = 10;
+
+/// Built-in lesson packs, by name.
+pub const LESSONS: [(&str, &str); 6] = [
+    ("lifetimes", include_str!("../lessons/lifetimes.txt")),
+    ("generics", include_str!("../lessons/generics.txt")),
+    ("closures", include_str!("../lessons/closures.txt")),
+    ("macros", include_str!("../lessons/macros.txt")),
+    ("patterns", include_str!("../lessons/patterns.txt")),
+    ("async", include_str!("../lessons/async.txt")),
+];
+
+/// An ordered set of lines drilling one piece of syntax.
+///
+/// Packs are plain text: `# title:` and `# goal:` headers followed by one
+/// line to type per line of the file.
+#[derive(Debug,Clone)]
+pub struct Lesson {
+    pub title: String,
+    pub goal: String,
+    pub lines: Vec<String>,
+}
+
+impl Lesson {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut title = None;
+        let mut goal = None;
+        let mut lines = Vec::new();
+        for line in text.lines().map(|s| s.trim()).filter(|s| !s.is_empty()) {
+            if let Some(s) = line.strip_prefix("# title:") {
+                title = Some(s.trim().to_owned());
+            } else if let Some(s) = line.strip_prefix("# goal:") {
+                goal = Some(s.trim().to_owned());
+            } else if line.chars().count() > MAX_LINE_LEN {
+                bail!("lesson line is longer than {} chars: {}", MAX_LINE_LEN, line);
+            } else {
+                lines.push(line.to_owned());
+            }
+        }
+        let title = title.context("lesson has no `# title:` header")?;
+        if lines.is_empty() {
+            bail!("lesson {} has no lines", title);
+        }
+        Ok(Self {
+            title,
+            goal: goal.unwrap_or_default(),
+            lines,
+        })
+    }
+
+    /// Looks up a built-in pack by name, otherwise reads `name` as a file.
+    pub fn load(name: &str) -> Result<Self> {
+        let text = match LESSONS.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            Some((_, text)) => text.to_string(),
+            None => std::fs::read_to_string(name).with_context(|| {
+                let names: Vec<_> = LESSONS.iter().map(|(n, _)| *n).collect();
+                format!("{} is neither a lesson file nor one of {}", name, names.join(","))
+            })?,
+        };
+        Self::parse(&text)
+    }
+
+    /// A challenge of lines picked from every built-in pack. The same seed
+    /// always picks the same lines in the same order.
+    pub fn daily(seed: u64) -> Result<Self> {
+        let mut corpus = Vec::new();
+        for (_, text) in LESSONS.iter() {
+            corpus.extend(Self::parse(text)?.lines);
+        }
+        let mut rng = Rng::new(seed);
+        let mut lines = Vec::new();
+        while lines.len() < DAILY_LINES.min(corpus.len()) {
+            lines.push(corpus.swap_remove(rng.below(corpus.len())));
+        }
+        Ok(Self {
+            title: "Daily challenge".to_owned(),
+            goal: format!("seed {}", seed),
+            lines,
+        })
+    }
+}
+
+/// Serves a lesson's lines in order, tagging each with the lesson progress.
+#[derive(Debug)]
+pub struct LessonSource {
+    lesson: Lesson,
+    lines: VecDeque<String>,
+}
+
+impl LessonSource {
+    pub fn new(lesson: Lesson) -> Self {
+        debug!("Starting lesson: {}", lesson.title);
+        Self {
+            lines: lesson.lines.iter().cloned().collect(),
+            lesson,
+        }
+    }
+}
+
+#[async_trait]
+impl LineSource for LessonSource {
+    async fn get_line(&mut self) -> Result<Option<SrcString>> {
+        let total = self.lesson.lines.len();
+        Ok(self.lines.pop_front().map(|string| {
+            let n = total - self.lines.len();
+            let source = if self.lesson.goal.is_empty() {
+                format!("{} ({}/{})", self.lesson.title, n, total)
+            } else {
+                format!("{} ({}/{}) - {}", self.lesson.title, n, total, self.lesson.goal)
+            };
+            SrcString {
+                string,
+                source,
+            }
+        }))
+    }
+}
@@ -1,18 +1,42 @@
-use std::collections::VecDeque;
+use anyhow::Result;
+use async_trait::async_trait;
 
-use anyhow::{bail,Result};
-
-use tokio::sync::mpsc::{channel,Sender,WeakSender};
+use tokio::sync::mpsc::{channel,Receiver,Sender};
 use tokio::task::JoinHandle;
 
 use tracing::{debug,Level};
 use tracing_subscriber::{self as ts, EnvFilter};
 use tracing_appender as ta;
 
-use crate::fetch::*;
+use crate::command::{CommandSource,StdinSource};
+use crate::config::Source;
+use crate::fetch::CratesIo;
+use crate::generator::Generator;
+use crate::lessons::{Lesson,LessonSource};
+use crate::rng::Rng;
+use crate::rust_src::RustSrc;
+
+/// Number of lines buffered between the queue and the game.
+pub const QUEUE_LEN: usize = 10;
+
+/// Lines longer than this can't be centered on the minimum terminal width.
+pub const MAX_LINE_LEN: usize = 80;
+
+/// Whether a trimmed line of Rust source is worth typing.
+pub fn is_code_line(s: &str) -> bool {
+    s.len() >= 10 && s.len() <= MAX_LINE_LEN
+    &&
+    !s.starts_with("//")
+}
+
+/// Columns of indentation before the text of a line, counting a tab as four.
+pub fn indent_width(s: &str) -> usize {
+    s.chars().take_while(|c| c.is_whitespace()).map(|c| if c == '\t' { 4 } else { 1 }).sum()
+}
 
 #[derive(Debug)]
 pub struct SrcString {
+    /// The line with its indentation, for the editor mode to show.
     pub string: String,
     pub source: String,
 }
@@ -26,6 +50,51 @@ impl Default for SrcString {
     }
 }
 
+impl SrcString {
+    /// Parses a line in the `text ::: source` format printed by `dump`,
+    /// falling back to `default_source` when no source is given. The text
+    /// keeps its indentation.
+    pub fn parse(line: &str, default_source: &str) -> Option<Self> {
+        // the source is split off the right, the text may contain anything
+        let (string, source) = match line.rsplit_once(" ::: ") {
+            Some((string, source)) => (string.trim_end(), source.trim()),
+            None => (line.trim_end(), default_source),
+        };
+        let text = string.trim_start();
+        if text.is_empty() || text.chars().count() > MAX_LINE_LEN {
+            return None;
+        }
+        Some(Self {
+            string: string.to_owned(),
+            source: source.to_owned(),
+        })
+    }
+}
+
+#[async_trait]
+pub trait LineSource: Send {
+    /// Returns the next line, or `None` once the source is exhausted.
+    async fn get_line(&mut self) -> Result<Option<SrcString>>;
+
+    /// Moves on from the current repository or file when the player asks
+    /// for something different. Sources implementing this are listed in
+    /// `Source::can_reroll`.
+    fn reroll(&mut self) {}
+}
+
+/// Opens `source`, seeding any randomness in it with `seed`.
+pub async fn open_source(source: &Source, seed: u64) -> Result<Box<dyn LineSource>> {
+    Ok(match source {
+        Source::Crates => Box::new(CratesIo::default()),
+        Source::Command(cmd) => Box::new(CommandSource::new(cmd)?),
+        Source::Stdin => Box::new(StdinSource::new()?),
+        Source::RustSrc(crates) => Box::new(RustSrc::new(crates, Rng::new(seed)).await?),
+        Source::Lesson(lesson) => Box::new(LessonSource::new(lesson.clone())),
+        Source::Generate(constructs) => Box::new(Generator::new(constructs.clone(), Rng::new(seed))),
+        Source::Daily => Box::new(LessonSource::new(Lesson::daily(seed)?)),
+    })
+}
+
 fn init_log(prefix: &str) -> Result<ta::non_blocking::WorkerGuard> {
     let log_dir = std::env::var("CARGO_MANIFEST_DIR")?;
     let (file, guard) = ta::non_blocking(ta::rolling::daily(log_dir,prefix));
@@ -41,22 +110,24 @@ fn init_log(prefix: &str) -> Result<ta::non_blocking::WorkerGuard> {
     Ok(guard)
 }
 
-#[derive(Debug)]
 pub struct LineQueue {
-    repos: VecDeque<Repo>,
-    weak_tx: WeakSender<SrcString>,
-    page_no: u32,
+    source: Box<dyn LineSource>,
+    tx: Sender<SrcString>,
+    reroll_rx: Receiver<()>,
     _trace_guard: ta::non_blocking::WorkerGuard,
 }
 
 impl LineQueue {
-    pub fn new(tx: Sender<SrcString>) -> JoinHandle<Result<()>> {
+    /// Spawns the queue filling `tx` from `source`. The channel closes once
+    /// the source runs out of lines. Anything sent on `reroll_rx` rerolls the
+    /// source before the next line.
+    pub fn spawn(tx: Sender<SrcString>, reroll_rx: Receiver<()>, source: Source, seed: u64) -> JoinHandle<Result<()>> {
         tokio::spawn( async move {
             let _trace_guard = init_log("rt_log")?;
             let line_queue = Self {
-                repos: VecDeque::new(),
-                weak_tx: tx.downgrade(),
-                page_no: 1,
+                source: open_source(&source, seed).await?,
+                tx,
+                reroll_rx,
                 _trace_guard,
             };
             line_queue.init().await
@@ -65,47 +136,77 @@ impl LineQueue {
 
     async fn init(mut self) -> Result<()> {
         loop {
-            let line = match self.repos.pop_front() {
-                Some(mut repo) => {
-                    if let Some(line) = repo.get_line().await? {
-                        self.repos.push_front(repo);
-                        Some(line)
-                    } else {
-                        None
-                    }
-                },
-                None => {
-                    // fetch more repos
-                    self.repos.extend(get_repo_urls(self.page_no).await?);
-                    self.page_no += 1;
-                    None
-                },
-            };
-            if let Some(line) = line {
-                if let Some(tx) = self.weak_tx.clone().upgrade() {
-                    let permit = tx.reserve().await;
-                    if tx.is_closed() { break; }
-                    permit?.send(line);
-                } else {
-                    break;
-                }
+            if self.reroll_rx.try_recv().is_ok() {
+                debug!("Rerolling line source");
+                self.source.reroll();
             }
+            let line = match self.source.get_line().await? {
+                Some(x) => x,
+                None => break,
+            };
+            let permit = self.tx.reserve().await;
+            if self.tx.is_closed() { break; }
+            permit?.send(line);
         }
         Ok(())
     }
 }
 
 #[tokio::main]
-pub async fn dump() -> Result<()> {
-    let (tx, mut rx) = channel::<SrcString>(10);
-    let line_queue = LineQueue::new(tx.clone());
+pub async fn dump(source: Source, seed: u64) -> Result<()> {
+    let (tx, mut rx) = channel::<SrcString>(QUEUE_LEN);
+    let (_reroll_tx, reroll_rx) = channel(1);
+    let line_queue = LineQueue::spawn(tx, reroll_rx, source, seed);
     for _ in 0..100 {
         let line = match rx.recv().await {
             Some(x) => x,
-            None => bail!("channel is closed"),
+            None => break,
         };
         println!("{} ::: {}",line.string, line.source);
     }
     rx.close();
     line_queue.await?
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_without_source_uses_default() {
+        let src_str = SrcString::parse("let x = 1;  ", "stdin").unwrap();
+        assert_eq!(src_str.string, "let x = 1;");
+        assert_eq!(src_str.source, "stdin");
+    }
+
+    #[test]
+    fn parse_dump_format() {
+        let src_str = SrcString::parse("let x = 1; ::: serde/src/lib.rs", "stdin").unwrap();
+        assert_eq!(src_str.string, "let x = 1;");
+        assert_eq!(src_str.source, "serde/src/lib.rs");
+    }
+
+    #[test]
+    fn parse_splits_on_last_separator() {
+        let src_str = SrcString::parse("a ::: b ::: src", "stdin").unwrap();
+        assert_eq!(src_str.string, "a ::: b");
+        assert_eq!(src_str.source, "src");
+    }
+
+    #[test]
+    fn parse_keeps_indentation() {
+        let src_str = SrcString::parse("    let x = 1; ::: src", "stdin").unwrap();
+        assert_eq!(src_str.string, "    let x = 1;");
+        assert_eq!(indent_width(&src_str.string), 4);
+        assert_eq!(indent_width("\t\tx"), 8);
+    }
+
+    #[test]
+    fn parse_skips_empty_and_long_lines() {
+        assert!(SrcString::parse("   ", "stdin").is_none());
+        assert!(SrcString::parse(" ::: src", "stdin").is_none());
+        assert!(SrcString::parse(&"x".repeat(MAX_LINE_LEN + 1), "stdin").is_none());
+        assert!(SrcString::parse(&"x".repeat(MAX_LINE_LEN), "stdin").is_some());
+        assert!(SrcString::parse(&format!("    {}", "x".repeat(MAX_LINE_LEN)), "stdin").is_some());
+    }
+}
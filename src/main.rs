@@ -1,18 +1,33 @@
+mod command;
+mod config;
 mod fetch;
 mod line_queue;
 mod game;
+mod generator;
+mod ghost;
+mod heatmap;
+mod history;
+mod lessons;
+mod race;
+mod replay;
+mod rng;
+mod rust_src;
+mod stats;
+mod store;
+mod tokens;
 mod tui;
 
+use config::Config;
+
 fn main() {
-    let args : Vec<String> = std::env::args().collect();
-    match if args.len() > 1 {
-        if args[1] == "dump" {
-           line_queue::dump()
-        } else {
-            Err(anyhow::anyhow!("argument not recognized"))
-        }
-    } else {
-        game::run()
+    let args : Vec<String> = std::env::args().skip(1).collect();
+    match match args.first().map(|s| s.as_str()) {
+        Some("dump") => Config::parse(&args[1..]).and_then(|config| line_queue::dump(config.source, config.seed)),
+        Some("replay") => replay::Replay::parse(&args[1..]).and_then(replay::run),
+        Some("host") => race::Host::parse(&args[1..]).and_then(race::host),
+        Some("join") => race::Join::parse(&args[1..]).and_then(race::join),
+        Some("stats") => history::Stats::parse(&args[1..]).and_then(history::stats),
+        _ => Config::parse(&args).and_then(game::run),
     } {
         Ok(_) => {},
         Err(e) => {
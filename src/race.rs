@@ -0,0 +1,360 @@
+use std::sync::{Arc,Mutex};
+use std::time::Duration;
+
+use anyhow::{anyhow,bail,Context,Result};
+
+use crossterm::event::{EventStream,Event};
+
+use futures::StreamExt;
+
+use serde::{Deserialize,Serialize};
+
+use tokio::io::{AsyncBufReadExt,AsyncWriteExt,BufReader,Lines};
+use tokio::net::{TcpListener,TcpStream,tcp::{OwnedReadHalf,OwnedWriteHalf}};
+use tokio::sync::{broadcast,mpsc::{unbounded_channel,UnboundedSender},watch};
+use tokio::{select,time::interval};
+
+use tracing::debug;
+
+use crate::config::{Config,Mode,Source};
+use crate::game::{self,is_quit};
+use crate::lessons::Lesson;
+use crate::line_queue::open_source;
+use crate::tui::show_racers;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// Lines in a race when `--lines` isn't given.
+pub const DEFAULT_RACE_LINES: usize = 5;
+
+/// How often progress is reported and the racers redrawn.
+const UPDATE_MS: u64 = 250;
+
+/// One racer's progress through the race.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct Racer {
+    pub name: String,
+    /// Correctly typed characters so far.
+    pub chars: usize,
+    pub wpm: f32,
+    pub done: bool,
+}
+
+/// The protocol, sent as one JSON object per line.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    /// Client to host, once on connecting.
+    Join { name: String },
+    /// Host to clients, when the host starts the race.
+    Start { host: String, lines: Vec<String> },
+    /// Client to host, while racing.
+    Progress { chars: usize, wpm: f32, done: bool },
+    /// Host to clients, while racing.
+    Racers { racers: Vec<Racer> },
+}
+
+async fn send(write: &mut OwnedWriteHalf, message: &Message) -> Result<()> {
+    let mut json = serde_json::to_string(message)?;
+    json.push('\n');
+    write.write_all(json.as_bytes()).await?;
+    Ok(())
+}
+
+async fn recv(lines: &mut Lines<BufReader<OwnedReadHalf>>) -> Result<Option<Message>> {
+    match lines.next_line().await? {
+        Some(line) => Ok(Some(serde_json::from_str(&line).with_context(|| format!("invalid message: {}", line))?)),
+        None => Ok(None),
+    }
+}
+
+/// The game's side of a race: where it reports progress and reads everyone's.
+pub struct RaceLink {
+    /// Characters in the whole race.
+    pub total: usize,
+    progress: UnboundedSender<Message>,
+    racers: watch::Receiver<Vec<Racer>>,
+}
+
+impl RaceLink {
+    pub fn report(&self, chars: usize, wpm: f32, done: bool) {
+        // the connection dropping ends the updates, not the local game
+        let _ = self.progress.send(Message::Progress { chars, wpm, done });
+    }
+
+    pub fn show(&self, cols: u16) -> Result<()> {
+        show_racers(cols, &self.racers.borrow(), self.total)
+    }
+
+    /// Keeps the racers on screen until they've all finished or the player quits.
+    pub async fn wait_for_others(&mut self, reader: &mut EventStream, cols: u16) -> Result<()> {
+        loop {
+            self.show(cols)?;
+            if self.racers.borrow().iter().all(|r| r.done) {
+                return Ok(());
+            }
+            select! {
+                changed = self.racers.changed() => if changed.is_err() { return Ok(()) },
+                event = reader.next() => match event {
+                    Some(Ok(Event::Key(key))) if is_quit(&key) => return Ok(()),
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(()),
+                    _ => (),
+                },
+            }
+        }
+    }
+}
+
+fn race_lesson(host: &str, lines: Vec<String>) -> Lesson {
+    Lesson {
+        title: "LAN race".to_owned(),
+        goal: format!("hosted by {}", host),
+        lines,
+    }
+}
+
+fn default_name() -> String {
+    std::env::var("USER").unwrap_or_else(|_| "player".to_owned())
+}
+
+#[derive(Debug)]
+pub struct Host {
+    pub port: u16,
+    pub name: String,
+    pub config: Config,
+}
+
+impl Host {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut port = DEFAULT_PORT;
+        let mut name = default_name();
+        let mut rest = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {}", arg));
+            match arg.as_str() {
+                "--port" => port = value()?.parse().map_err(|_| anyhow!("invalid port"))?,
+                "--name" => name = value()?.to_owned(),
+                _ => rest.push(arg.to_owned()),
+            }
+        }
+        Ok(Self {
+            port,
+            name,
+            config: Config::parse(&rest)?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct Join {
+    pub addr: String,
+    pub name: String,
+    pub config: Config,
+}
+
+impl Join {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut addr = None;
+        let mut name = default_name();
+        let mut rest = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {}", arg));
+            match arg.as_str() {
+                "--name" => name = value()?.to_owned(),
+                _ if addr.is_none() && !arg.starts_with("--") => addr = Some(arg.to_owned()),
+                _ => rest.push(arg.to_owned()),
+            }
+        }
+        let mut addr = addr.context("missing host address to join")?;
+        if !addr.contains(':') {
+            addr = format!("{}:{}", addr, DEFAULT_PORT);
+        }
+        Ok(Self {
+            addr,
+            name,
+            config: Config::parse(&rest)?,
+        })
+    }
+}
+
+/// Takes the race lines from the configured source.
+async fn pick_lines(config: &Config, count: usize) -> Result<Vec<String>> {
+    // read straight from the source, the game spawns the line queue later
+    let mut source = open_source(&config.source, config.seed).await?;
+    let mut lines = Vec::new();
+    while lines.len() < count {
+        match source.get_line().await? {
+            Some(line) => lines.push(line.string.trim_start().to_owned()),
+            None => break,
+        }
+    }
+    if lines.is_empty() {
+        bail!("the source has no lines to race on");
+    }
+    Ok(lines)
+}
+
+fn update(racers: &Mutex<Vec<Racer>>, index: usize, chars: usize, wpm: f32, done: bool) {
+    if let Ok(mut racers) = racers.lock() {
+        let racer = &mut racers[index];
+        racer.chars = chars;
+        racer.wpm = wpm;
+        racer.done = done;
+    }
+}
+
+/// Serves one client: registers it, then relays broadcasts out and progress in.
+async fn serve_racer(stream: TcpStream, racers: Arc<Mutex<Vec<Racer>>>, mut broadcasts: broadcast::Receiver<String>) -> Result<()> {
+    let addr = stream.peer_addr()?;
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let name = match recv(&mut lines).await? {
+        Some(Message::Join { name }) => name,
+        _ => bail!("{} didn't join", addr),
+    };
+    println!("{} joined from {}", name, addr);
+    let index = {
+        let mut racers = racers.lock().map_err(|_| anyhow!("racers lock poisoned"))?;
+        racers.push(Racer { name, chars: 0, wpm: 0.0, done: false });
+        racers.len() - 1
+    };
+    tokio::spawn(async move {
+        loop {
+            match broadcasts.recv().await {
+                Ok(json) => if write.write_all(json.as_bytes()).await.is_err() { break },
+                // a slow client only misses progress updates
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    while let Some(message) = recv(&mut lines).await? {
+        if let Message::Progress { chars, wpm, done } = message {
+            update(&racers, index, chars, wpm, done);
+        }
+    }
+    // a racer who leaves counts as finished, so nobody waits on them
+    if let Ok(mut racers) = racers.lock() {
+        racers[index].done = true;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn host(host: Host) -> Result<()> {
+    let Host { port, name, mut config } = host;
+    let count = match config.mode {
+        Mode::Lines(n) => n,
+        _ => DEFAULT_RACE_LINES,
+    };
+    let lines = pick_lines(&config, count).await?;
+    let total = lines.iter().map(|l| l.chars().count()).sum();
+
+    let listener = TcpListener::bind(("0.0.0.0", port)).await
+        .with_context(|| format!("failed to listen on port {}", port))?;
+    let racers = Arc::new(Mutex::new(vec![Racer { name: name.clone(), chars: 0, wpm: 0.0, done: false }]));
+    let (broadcast_tx, _) = broadcast::channel::<String>(64);
+
+    let accept = {
+        let racers = racers.clone();
+        let broadcast_tx = broadcast_tx.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let racers = racers.clone();
+                let broadcasts = broadcast_tx.subscribe();
+                tokio::spawn(async move {
+                    if let Err(e) = serve_racer(stream, racers, broadcasts).await {
+                        debug!("Racer disconnected: {:#}", e);
+                    }
+                });
+            }
+        })
+    };
+
+    println!("Hosting a {} line race on port {}. Others join with `rustic-typster join <this machine>`.", lines.len(), port);
+    println!("Press Enter to start.");
+    BufReader::new(tokio::io::stdin()).lines().next_line().await?;
+    accept.abort();
+
+    let mut start = serde_json::to_string(&Message::Start { host: name.clone(), lines: lines.clone() })?;
+    start.push('\n');
+    let _ = broadcast_tx.send(start);
+
+    // the host's own progress goes straight into the table
+    let (progress_tx, mut progress_rx) = unbounded_channel();
+    {
+        let racers = racers.clone();
+        tokio::spawn(async move {
+            while let Some(Message::Progress { chars, wpm, done }) = progress_rx.recv().await {
+                update(&racers, 0, chars, wpm, done);
+            }
+        });
+    }
+    let (racers_tx, racers_rx) = watch::channel(Vec::new());
+    tokio::spawn(async move {
+        let mut ticker = interval(Duration::from_millis(UPDATE_MS));
+        loop {
+            ticker.tick().await;
+            let snapshot = match racers.lock() {
+                Ok(racers) => racers.clone(),
+                Err(_) => break,
+            };
+            if let Ok(mut json) = serde_json::to_string(&Message::Racers { racers: snapshot.clone() }) {
+                json.push('\n');
+                let _ = broadcast_tx.send(json);
+            }
+            if racers_tx.send(snapshot).is_err() {
+                break;
+            }
+        }
+    });
+
+    config.mode = Mode::Lines(lines.len());
+    config.source = Source::Lesson(race_lesson(&name, lines));
+    game::play(config, Some(RaceLink { total, progress: progress_tx, racers: racers_rx })).await
+}
+
+#[tokio::main]
+pub async fn join(join: Join) -> Result<()> {
+    let Join { addr, name, mut config } = join;
+    let stream = TcpStream::connect(&addr).await.with_context(|| format!("failed to connect to {}", addr))?;
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    send(&mut write, &Message::Join { name }).await?;
+    println!("Joined the race at {}, waiting for the host to start.", addr);
+
+    let (host, race_lines) = loop {
+        match recv(&mut lines).await? {
+            Some(Message::Start { host, lines }) => break (host, lines),
+            Some(_) => continue,
+            None => bail!("the host closed the connection before the race started"),
+        }
+    };
+    let total = race_lines.iter().map(|l| l.chars().count()).sum();
+
+    let (progress_tx, mut progress_rx) = unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(message) = progress_rx.recv().await {
+            if send(&mut write, &message).await.is_err() {
+                break;
+            }
+        }
+    });
+    let (racers_tx, racers_rx) = watch::channel(Vec::new());
+    tokio::spawn(async move {
+        while let Ok(Some(message)) = recv(&mut lines).await {
+            if let Message::Racers { racers } = message {
+                if racers_tx.send(racers).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+
+    config.mode = Mode::Lines(race_lines.len());
+    config.source = Source::Lesson(race_lesson(&host, race_lines));
+    game::play(config, Some(RaceLink { total, progress: progress_tx, racers: racers_rx })).await
+}
@@ -0,0 +1,299 @@
+use std::fs::OpenOptions;
+use std::io::{ErrorKind,Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow,bail,Context,Result};
+
+use crossterm::event::{EventStream,Event,KeyCode,KeyEvent};
+
+use futures::StreamExt;
+
+use serde::{Deserialize,Serialize};
+
+use tokio::{select,time::Instant};
+
+use crate::game::{auto_closer,wait_until};
+use crate::stats::{KeyKind,KeyPress,ShownLine,TypingStats};
+use crate::store::{data_dir,unix_millis};
+use crate::tui::*;
+
+/// Seconds skipped by the seek keys.
+const SEEK_MS: u32 = 5_000;
+
+fn sessions_dir() -> Result<PathBuf> {
+    let dir = data_dir()?.join("sessions");
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// Everything needed to play a session back.
+#[derive(Debug,Serialize,Deserialize)]
+pub struct Recording {
+    pub label: String,
+    pub editor: bool,
+    pub shown: Vec<ShownLine>,
+    pub events: Vec<KeyPress>,
+}
+
+impl Recording {
+    pub fn new(label: String, editor: bool, stats: &TypingStats) -> Self {
+        Self {
+            label,
+            editor,
+            shown: stats.shown.clone(),
+            events: stats.events.clone(),
+        }
+    }
+
+    /// Saves to the sessions directory, named after the current time in
+    /// milliseconds. Never overwrites an earlier session.
+    pub fn save(&self) -> Result<PathBuf> {
+        let dir = sessions_dir()?;
+        let json = serde_json::to_string(self)?;
+        let mut millis = unix_millis();
+        loop {
+            let path = dir.join(format!("{}.json", millis));
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    file.write_all(json.as_bytes())
+                        .with_context(|| format!("failed to write {}", path.display()))?;
+                    return Ok(path);
+                },
+                // a session saved in the same millisecond takes the next one
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => millis += 1,
+                Err(e) => return Err(e).with_context(|| format!("failed to create {}", path.display())),
+            }
+        }
+    }
+
+    /// Loads `last`, a session name from the sessions directory, or a file.
+    pub fn load(name: &str) -> Result<Self> {
+        let dir = sessions_dir()?;
+        let path = if name == "last" {
+            let mut names: Vec<_> = std::fs::read_dir(&dir)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+                .collect();
+            names.sort();
+            names.pop().with_context(|| format!("no sessions recorded in {}", dir.display()))?
+        } else if dir.join(format!("{}.json", name)).is_file() {
+            dir.join(format!("{}.json", name))
+        } else {
+            PathBuf::from(name)
+        };
+        let json = std::fs::read_to_string(&path).with_context(|| format!("failed to read session {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("failed to parse session {}", path.display()))
+    }
+
+    fn length_ms(&self) -> u32 {
+        let last_shown = self.shown.last().map_or(0, |s| s.time_ms);
+        let last_key = self.events.last().map_or(0, |e| e.time_ms);
+        last_shown.max(last_key)
+    }
+}
+
+#[derive(Debug)]
+pub struct Replay {
+    pub session: String,
+    pub speed: f32,
+}
+
+impl Replay {
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut session = None;
+        let mut speed = 1.0;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {}", arg));
+            match arg.as_str() {
+                "--speed" => speed = parse_speed(value()?)?,
+                _ if session.is_none() && !arg.starts_with("--") => session = Some(arg.to_owned()),
+                _ => bail!("argument not recognized: {}", arg),
+            }
+        }
+        Ok(Self {
+            session: session.unwrap_or_else(|| "last".to_owned()),
+            speed,
+        })
+    }
+}
+
+fn parse_speed(value: &str) -> Result<f32> {
+    match value.parse::<f32>() {
+        Ok(speed) if speed > 0.0 && speed.is_finite() => Ok(speed),
+        _ => bail!("invalid speed {}, expected a positive number", value),
+    }
+}
+
+/// A line appearing or a key press, in the order they happened.
+#[derive(Debug,Clone,Copy)]
+enum Step {
+    Show(usize),
+    Key(usize),
+}
+
+fn timeline(recording: &Recording) -> Vec<(u32, Step)> {
+    let mut steps = Vec::new();
+    let mut shown = 0;
+    for (i, event) in recording.events.iter().enumerate() {
+        while shown <= event.line && shown < recording.shown.len() {
+            steps.push((recording.shown[shown].time_ms, Step::Show(shown)));
+            shown += 1;
+        }
+        steps.push((event.time_ms, Step::Key(i)));
+    }
+    for (i, line) in recording.shown.iter().enumerate().skip(shown) {
+        steps.push((line.time_ms, Step::Show(i)));
+    }
+    steps
+}
+
+/// Redraws the screen as the game drew it, one step at a time.
+struct Player<'a> {
+    cols: u16,
+    recording: &'a Recording,
+    pending: Vec<char>,
+}
+
+impl Player<'_> {
+    fn play(&mut self, step: Step) -> Result<()> {
+        match step {
+            Step::Show(i) => {
+                let line = &self.recording.shown[i];
+                display_current_line(self.cols,&line.line,&line.source,line.indent)?;
+                show_cursor()?;
+                self.pending.clear();
+            },
+            Step::Key(i) => match self.recording.events[i].kind {
+                KeyKind::Char { typed, correct, .. } => {
+                    type_char(typed,correct)?;
+                    if self.recording.editor {
+                        if self.pending.last() == Some(&typed) {
+                            self.pending.pop();
+                        } else if let Some(closer) = auto_closer(typed) {
+                            if !self.pending.contains(&'"') {
+                                self.pending.push(closer);
+                            }
+                        }
+                        show_pending(&self.pending.iter().rev().collect::<String>())?;
+                    }
+                },
+                KeyKind::Refused { .. } => (),
+                KeyKind::Backspace { removed, .. } => {
+                    backspace()?;
+                    if self.recording.editor {
+                        if auto_closer(removed).is_some() && self.pending.last() == auto_closer(removed).as_ref() {
+                            self.pending.pop();
+                        }
+                        show_pending(&self.pending.iter().rev().collect::<String>())?;
+                    }
+                },
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Session time shown at `now`, scaled by `speed` since `from_ms` at `since`.
+fn position_ms(from_ms: u32, since: Instant, speed: f32) -> u32 {
+    from_ms + (since.elapsed().as_millis() as f32 * speed) as u32
+}
+
+#[tokio::main]
+pub async fn run(replay: Replay) -> Result<()> {
+    let recording = Recording::load(&replay.session)?;
+    let steps = timeline(&recording);
+    let length_ms = recording.length_ms();
+
+    let _guard = setup_tui()?;
+    let cols = show_replay_intro(&recording.label)?;
+
+    let mut reader = EventStream::new();
+    let mut player = Player {
+        cols,
+        recording: &recording,
+        pending: Vec::new(),
+    };
+    let mut speed = replay.speed;
+    // playback runs from `from_ms` at `since`, or stands still at `from_ms` when paused
+    let mut from_ms = 0;
+    let mut since = Instant::now();
+    let mut paused = false;
+    // index of the next step to play
+    let mut next = 0;
+    let mut ticker = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        let now_ms = if paused { from_ms } else { position_ms(from_ms, since, speed) };
+        while next < steps.len() && steps[next].0 <= now_ms {
+            player.play(steps[next].1)?;
+            next += 1;
+        }
+        let due = match steps.get(next) {
+            Some((time_ms, _)) if !paused => {
+                Some(since + Duration::from_millis(((time_ms - from_ms) as f32 / speed) as u64))
+            },
+            _ => None,
+        };
+
+        let event = select! {
+            event = reader.next() => event,
+            _ = wait_until(due) => continue,
+            _ = ticker.tick() => {
+                let state = if paused { "paused" } else if next == steps.len() { "ended" } else { "playing" };
+                show_status(cols,&format!("{:.1}s / {:.1}s at {}x, {}", now_ms.min(length_ms) as f32 / 1000.0, length_ms as f32 / 1000.0, speed, state))?;
+                continue;
+            },
+        };
+
+        let mut seek_to = None;
+        match event {
+            Some(Ok(Event::Key(KeyEvent {code, ..}))) => match code {
+                KeyCode::Esc | KeyCode::Char('q') => break,
+                KeyCode::Char(' ') => {
+                    if paused {
+                        since = Instant::now();
+                    } else {
+                        from_ms = now_ms;
+                    }
+                    paused = !paused;
+                },
+                KeyCode::Char('+') => {
+                    from_ms = now_ms;
+                    since = Instant::now();
+                    speed *= 2.0;
+                },
+                KeyCode::Char('-') => {
+                    from_ms = now_ms;
+                    since = Instant::now();
+                    speed /= 2.0;
+                },
+                KeyCode::Left => seek_to = Some(now_ms.saturating_sub(SEEK_MS)),
+                KeyCode::Right => seek_to = Some((now_ms + SEEK_MS).min(length_ms)),
+                KeyCode::Home => seek_to = Some(0),
+                _ => (),
+            },
+            Some(Ok(_)) => (),
+            Some(Err(e)) => return Err(e.into()),
+            None => break,
+        }
+
+        if let Some(target) = seek_to {
+            // redraw the line shown at the target, then replay its keys up to there
+            let played = steps.iter().take_while(|(t, _)| *t <= target).count();
+            next = steps[..played].iter().rposition(|(_, s)| matches!(s, Step::Show(_))).unwrap_or(0);
+            clear_replay_line()?;
+            while next < played {
+                player.play(steps[next].1)?;
+                next += 1;
+            }
+            from_ms = target;
+            since = Instant::now();
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::collections::VecDeque;
+use std::path::{Path,PathBuf};
+
+use anyhow::{bail,Context,Result};
+use async_trait::async_trait;
+
+use tokio::fs;
+use tokio::process::Command;
+
+use tracing::{debug,trace};
+
+use crate::line_queue::{is_code_line,LineSource,SrcString};
+use crate::rng::Rng;
+
+pub const RUST_SRC_CRATES: [&str; 3] = ["core", "alloc", "std"];
+
+/// Finds the `library/` tree installed by the `rust-src` rustup component
+/// for the active toolchain.
+async fn library_dir() -> Result<PathBuf> {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".into());
+    let output = Command::new(&rustc)
+        .args(["--print", "sysroot"])
+        .output()
+        .await
+        .with_context(|| format!("failed to run `{} --print sysroot`", rustc))?;
+    if !output.status.success() {
+        bail!("`{} --print sysroot` exited with {}", rustc, output.status);
+    }
+    let sysroot = String::from_utf8(output.stdout)?;
+    let library = Path::new(sysroot.trim()).join("lib/rustlib/src/rust/library");
+    if !library.is_dir() {
+        bail!("rust-src not found at {}, try `rustup component add rust-src`", library.display());
+    }
+    Ok(library)
+}
+
+/// Yields lines from the standard library sources, one crate after another.
+/// Files and directories are visited in an order shuffled by the seed.
+#[derive(Debug)]
+pub struct RustSrc {
+    library: PathBuf,
+    rng: Rng,
+    dirs: Vec<PathBuf>,
+    files: VecDeque<PathBuf>,
+    lines: VecDeque<String>,
+    source: String,
+}
+
+impl RustSrc {
+    pub async fn new(crates: &[String], rng: Rng) -> Result<Self> {
+        let library = library_dir().await?;
+        debug!("Reading rust-src from {}", library.display());
+        // dirs is used as a stack, so push the first crate last
+        let dirs = crates.iter().rev().map(|c| library.join(c).join("src")).collect();
+        Ok(Self {
+            library,
+            rng,
+            dirs,
+            files: VecDeque::new(),
+            lines: VecDeque::new(),
+            source: String::new(),
+        })
+    }
+
+    async fn read_dir(&mut self, dir: &Path) -> Result<()> {
+        trace!("Reading dir: {}", dir.display());
+        let mut entries = fs::read_dir(dir).await
+            .with_context(|| format!("failed to read {}", dir.display()))?;
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "rs") {
+                files.push(path);
+            }
+        }
+        // sort first, read_dir's order isn't stable and the seed should reproduce a session
+        files.sort();
+        dirs.sort();
+        self.rng.shuffle(&mut files);
+        self.rng.shuffle(&mut dirs);
+        self.files.extend(files);
+        self.dirs.extend(dirs);
+        Ok(())
+    }
+
+    async fn read_file(&mut self, file: &Path) -> Result<()> {
+        trace!("Reading file: {}", file.display());
+        let contents = fs::read_to_string(file).await?;
+        self.lines = contents.lines().map(|s| s.trim_end()).filter(|s| {
+            is_code_line(s.trim_start())
+        }).map(|s| {
+            s.to_owned()
+        }).collect();
+        self.source = file.strip_prefix(&self.library).unwrap_or(file).display().to_string();
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl LineSource for RustSrc {
+    async fn get_line(&mut self) -> Result<Option<SrcString>> {
+        loop {
+            if let Some(string) = self.lines.pop_front() {
+                return Ok(Some(SrcString {
+                    string,
+                    source: self.source.clone(),
+                }));
+            }
+            if let Some(file) = self.files.pop_front() {
+                self.read_file(&file).await?;
+            } else if let Some(dir) = self.dirs.pop() {
+                self.read_dir(&dir).await?;
+            } else {
+                return Ok(None);
+            }
+        }
+    }
+
+    fn reroll(&mut self) {
+        self.lines.clear();
+    }
+}
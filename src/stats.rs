@@ -0,0 +1,408 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize,Serialize};
+
+/// Characters per word in the standard WPM definition.
+pub const CHARS_PER_WORD: f32 = 5.0;
+
+/// A pause between keystrokes on a line at least this long is a hesitation.
+pub const HESITATION_MS: u32 = 1000;
+
+fn per_min(count: u32, time_ms: u32) -> f32 {
+    if time_ms == 0 {
+        0.0
+    } else {
+        count as f32 * 60_000.0 / time_ms as f32
+    }
+}
+
+fn percent(part: u32, total: u32) -> f32 {
+    if total == 0 {
+        100.0
+    } else {
+        part as f32 * 100.0 / total as f32
+    }
+}
+
+/// Keystroke counts for one line, or summed over a session.
+///
+/// Every typed character is a keystroke. A wrong keystroke is an error: it is
+/// corrected if it is later backspaced over and uncorrected if it is still
+/// there when the line ends. Keystrokes past the end of the line are extra
+/// characters instead, whether or not they're backspaced. Keystrokes the
+/// error policy refuses are errors too, so every error is in exactly one of
+/// the four.
+#[derive(Debug,Clone,Default,Serialize,Deserialize)]
+pub struct Keystrokes {
+    pub total: u32,
+    pub correct: u32,
+    pub corrected_errors: u32,
+    pub uncorrected_errors: u32,
+    pub extra_chars: u32,
+    #[serde(default)]
+    pub refused: u32,
+}
+
+impl Keystrokes {
+    pub fn errors(&self) -> u32 {
+        self.total - self.correct
+    }
+
+    /// Percentage of keystrokes that were correct.
+    pub fn accuracy(&self) -> f32 {
+        percent(self.correct, self.total)
+    }
+
+    fn add(&mut self, other: &Keystrokes) {
+        self.total += other.total;
+        self.correct += other.correct;
+        self.corrected_errors += other.corrected_errors;
+        self.uncorrected_errors += other.uncorrected_errors;
+        self.extra_chars += other.extra_chars;
+        self.refused += other.refused;
+    }
+}
+
+/// Results for a single completed line.
+#[derive(Debug,Clone,Default,Serialize,Deserialize)]
+pub struct LineStats {
+    pub line: String,
+    pub source: String,
+    /// Characters left in the typed text when the line ended.
+    pub chars: u32,
+    pub keys: Keystrokes,
+    pub time_ms: u32,
+}
+
+impl LineStats {
+    pub fn wpm(&self) -> f32 {
+        per_min(self.chars, self.time_ms) / CHARS_PER_WORD
+    }
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Skipped with the skip key or Enter on an empty line.
+    Skipped,
+    /// Skipped along with the rest of its source.
+    Rerolled,
+}
+
+/// A line that was served but not typed.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct SkippedLine {
+    pub line: String,
+    pub source: String,
+    pub reason: SkipReason,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KeyKind {
+    /// A typed character, `expected` is `None` past the end of the line.
+    Char { expected: Option<char>, typed: char, correct: bool },
+    /// A typed character the error policy didn't let through.
+    Refused { expected: Option<char>, typed: char },
+    /// A backspace over `removed`, typed where `expected` was.
+    Backspace { expected: Option<char>, removed: char },
+}
+
+/// One entry of the keystroke log.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Serialize,Deserialize)]
+pub struct KeyPress {
+    /// Milliseconds since the session started, not counting pauses.
+    pub time_ms: u32,
+    /// Index into the lines in the order they were shown.
+    pub line: usize,
+    /// Cursor position in the line before the key was pressed.
+    pub pos: usize,
+    #[serde(flatten)]
+    pub kind: KeyKind,
+}
+
+/// A line as it was shown to the player.
+#[derive(Debug,Clone,Serialize,Deserialize)]
+pub struct ShownLine {
+    pub line: String,
+    pub source: String,
+    /// Columns of indentation the editor mode showed before the line.
+    #[serde(default)]
+    pub indent: usize,
+    /// Session time the line appeared at, as in `KeyPress::time_ms`.
+    pub time_ms: u32,
+}
+
+#[derive(Default,Serialize,Deserialize)]
+pub struct TypingStats {
+    pub keys: Keystrokes,
+    pub total_chars: u32,
+    pub total_time_ms: u32,
+    /// Time from the first keystroke to the end of a timed session, which
+    /// speeds are measured over instead of the time spent on lines.
+    #[serde(default)]
+    pub session_ms: Option<u32>,
+    pub lines: Vec<LineStats>,
+    pub skipped: Vec<SkippedLine>,
+    /// Every line shown, including skipped ones and one left unfinished.
+    pub shown: Vec<ShownLine>,
+    /// Every key press, in order. Left out of the history, the session
+    /// recording keeps them.
+    #[serde(skip)]
+    pub events: Vec<KeyPress>,
+    #[serde(skip)]
+    current: Keystrokes,
+}
+
+impl TypingStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `line` was shown at `time_ms`.
+    pub fn show_line(&mut self, line: &str, source: &str, indent: usize, time_ms: u32) {
+        self.shown.push(ShownLine {
+            line: line.to_owned(),
+            source: source.to_owned(),
+            indent,
+            time_ms,
+        });
+    }
+
+    fn log(&mut self, time_ms: u32, pos: usize, kind: KeyKind) {
+        self.events.push(KeyPress {
+            time_ms,
+            line: self.shown.len().saturating_sub(1),
+            pos,
+            kind,
+        });
+    }
+
+    /// Records a character typed at `pos` against the `expected` one, which is
+    /// `None` past the end of the line. Returns whether the keystroke was correct.
+    pub fn type_char(&mut self, expected: Option<char>, typed: char, pos: usize, time_ms: u32) -> bool {
+        self.current.total += 1;
+        let correct = match expected {
+            Some(c) if c == typed => {
+                self.current.correct += 1;
+                true
+            },
+            Some(_) => false,
+            None => {
+                self.current.extra_chars += 1;
+                false
+            },
+        };
+        self.log(time_ms, pos, KeyKind::Char { expected, typed, correct });
+        correct
+    }
+
+    /// Records a character the error policy didn't let through. It counts as
+    /// a wrong keystroke even if it matches `expected`, since it was typed
+    /// before an earlier error was fixed.
+    pub fn refuse_char(&mut self, expected: Option<char>, typed: char, pos: usize, time_ms: u32) {
+        self.current.total += 1;
+        self.current.refused += 1;
+        self.log(time_ms, pos, KeyKind::Refused { expected, typed });
+    }
+
+    /// Records a backspace over `removed`, which was typed at `pos` where
+    /// `expected` was.
+    pub fn backspace(&mut self, expected: Option<char>, removed: char, pos: usize, time_ms: u32) {
+        // extra characters were already counted when typed
+        if expected.is_some_and(|c| c != removed) {
+            self.current.corrected_errors += 1;
+        }
+        self.log(time_ms, pos + 1, KeyKind::Backspace { expected, removed });
+    }
+
+    /// Finishes the current line, counting whatever in `typed` doesn't match
+    /// `line` as uncorrected errors. Anything past the end of `line` is
+    /// already counted as extra characters.
+    pub fn add_line(&mut self, line: &str, typed: &[char], time_elapsed_ms: u32) {
+        let mut keys = std::mem::take(&mut self.current);
+        keys.uncorrected_errors = typed.iter().zip(line.chars()).filter(|(&t, c)| t != *c).count() as u32;
+        let chars = typed.len() as u32;
+        self.keys.add(&keys);
+        self.total_chars += chars;
+        self.total_time_ms += time_elapsed_ms;
+        self.lines.push(LineStats {
+            line: line.to_owned(),
+            source: self.shown.last().map(|s| s.source.clone()).unwrap_or_default(),
+            chars,
+            keys,
+            time_ms: time_elapsed_ms,
+        });
+    }
+
+    /// Records a skipped line, dropping the keystrokes typed on it.
+    pub fn skip_line(&mut self, line: &str, source: &str, reason: SkipReason) {
+        self.current = Keystrokes::default();
+        self.skipped.push(SkippedLine {
+            line: line.to_owned(),
+            source: source.to_owned(),
+            reason,
+        });
+    }
+
+    /// The time speeds are measured over.
+    fn rate_ms(&self) -> u32 {
+        self.session_ms.unwrap_or(self.total_time_ms)
+    }
+
+    /// Characters per minute.
+    pub fn cpm(&self) -> f32 {
+        per_min(self.total_chars, self.rate_ms())
+    }
+
+    /// Gross WPM including `chars` typed over `time_ms` on a line in progress.
+    pub fn wpm_with(&self, chars: u32, time_ms: u32) -> f32 {
+        per_min(self.total_chars + chars, self.total_time_ms + time_ms) / CHARS_PER_WORD
+    }
+
+    /// Words per minute, counting every five characters as a word.
+    pub fn gross_wpm(&self) -> f32 {
+        self.cpm() / CHARS_PER_WORD
+    }
+
+    /// Gross WPM less one word per uncorrected error per minute.
+    pub fn net_wpm(&self) -> f32 {
+        (self.gross_wpm() - per_min(self.keys.uncorrected_errors, self.rate_ms())).max(0.0)
+    }
+
+    pub fn accuracy(&self) -> f32 {
+        self.keys.accuracy()
+    }
+
+    /// Key presses grouped by the line they were typed on.
+    fn line_events(&self) -> impl Iterator<Item = &[KeyPress]> {
+        self.events.chunk_by(|a, b| a.line == b.line)
+    }
+
+    /// How steady the speed was from second to second: 100% less the
+    /// coefficient of variation of correct characters per second. Seconds
+    /// without a key press while typing a line count as zero, time between
+    /// lines doesn't count.
+    pub fn consistency(&self) -> Option<f32> {
+        let mut per_sec = BTreeMap::<u32, u32>::new();
+        for events in self.line_events() {
+            let first = events[0].time_ms / 1000;
+            let last = events[events.len() - 1].time_ms / 1000;
+            for sec in first..=last {
+                per_sec.entry(sec).or_default();
+            }
+            for event in events {
+                if let KeyKind::Char { correct: true, .. } = event.kind {
+                    *per_sec.entry(event.time_ms / 1000).or_default() += 1;
+                }
+            }
+        }
+        if per_sec.len() < 2 {
+            return None;
+        }
+        let n = per_sec.len() as f32;
+        let mean = per_sec.values().sum::<u32>() as f32 / n;
+        let var = per_sec.values().map(|&c| (c as f32 - mean).powi(2)).sum::<f32>() / n;
+        if mean == 0.0 {
+            return None;
+        }
+        Some((100.0 - var.sqrt() / mean * 100.0).max(0.0))
+    }
+
+    /// Speed of the fastest whole word of three or more characters typed
+    /// without a mistake.
+    pub fn burst_wpm(&self) -> Option<f32> {
+        let mut best: Option<f32> = None;
+        for events in self.line_events() {
+            let line: Vec<char> = match self.shown.get(events[0].line) {
+                Some(shown) => shown.line.chars().collect(),
+                None => continue,
+            };
+            let is_space = |pos: usize| line.get(pos).is_none_or(|c| c.is_whitespace());
+            let mut word_start: Option<&KeyPress> = None;
+            for event in events {
+                match event.kind {
+                    KeyKind::Char { correct: true, .. } if !is_space(event.pos) => {
+                        if event.pos == 0 || is_space(event.pos - 1) {
+                            word_start = Some(event);
+                        }
+                        let start = match word_start {
+                            Some(start) => start,
+                            None => continue,
+                        };
+                        let intervals = (event.pos - start.pos) as u32;
+                        if is_space(event.pos + 1) && intervals >= 2 && event.time_ms > start.time_ms {
+                            let wpm = per_min(intervals, event.time_ms - start.time_ms) / CHARS_PER_WORD;
+                            best = Some(best.map_or(wpm, |b| b.max(wpm)));
+                        }
+                    },
+                    // anything but a correct key spoils the word
+                    _ => word_start = None,
+                }
+            }
+        }
+        best
+    }
+
+    /// Average time from a line appearing to its first keystroke.
+    pub fn reaction_ms(&self) -> Option<u32> {
+        let reactions: Vec<u32> = self.line_events()
+            .filter_map(|events| Some(events[0].time_ms - self.shown.get(events[0].line)?.time_ms))
+            .collect();
+        (reactions.iter().sum::<u32>()).checked_div(reactions.len() as u32)
+    }
+
+    /// Pauses of at least `HESITATION_MS` between keystrokes on a line.
+    pub fn hesitations(&self) -> usize {
+        self.line_events()
+            .flat_map(|events| events.windows(2))
+            .filter(|pair| pair[1].time_ms - pair[0].time_ms >= HESITATION_MS)
+            .count()
+    }
+
+    pub fn best_line(&self) -> Option<&LineStats> {
+        self.lines.iter().max_by(|a, b| a.wpm().total_cmp(&b.wpm()))
+    }
+
+    pub fn worst_line(&self) -> Option<&LineStats> {
+        self.lines.iter().min_by(|a, b| a.wpm().total_cmp(&b.wpm()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A line typed correctly with a key at each of `times_ms`.
+    fn typed_at(times_ms: &[u32]) -> TypingStats {
+        let mut stats = TypingStats::new();
+        stats.show_line(&"x".repeat(times_ms.len()), "test", 0, 0);
+        for (pos, &time_ms) in times_ms.iter().enumerate() {
+            stats.type_char(Some('x'), 'x', pos, time_ms);
+        }
+        stats
+    }
+
+    #[test]
+    fn steady_typing_is_fully_consistent() {
+        let stats = typed_at(&[0, 500, 1000, 1500, 2000, 2500]);
+        assert_eq!(stats.consistency(), Some(100.0));
+    }
+
+    #[test]
+    fn idle_seconds_within_a_line_count_against_consistency() {
+        // two keys a second, then three silent seconds
+        let stats = typed_at(&[0, 500, 1000, 1500, 5000, 5500]);
+        let consistency = stats.consistency().unwrap();
+        assert!(consistency < 50.0, "{}", consistency);
+    }
+
+    #[test]
+    fn timed_sessions_measure_speed_over_the_whole_test() {
+        let mut stats = typed_at(&[0, 500, 1000, 1500, 2000]);
+        stats.add_line("xxxxx", &['x'; 5], 2000);
+        assert_eq!(stats.cpm(), 150.0);
+        stats.session_ms = Some(60_000);
+        assert_eq!(stats.cpm(), 5.0);
+    }
+}
@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+use std::time::{SystemTime,UNIX_EPOCH};
+
+use anyhow::{Context,Result};
+
+/// `$XDG_DATA_HOME/rustic-typster`, falling back to `~/.local/share/rustic-typster`.
+pub fn data_dir() -> Result<PathBuf> {
+    let base = match std::env::var_os("XDG_DATA_HOME").filter(|s| !s.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let home = std::env::var_os("HOME").context("neither XDG_DATA_HOME nor HOME is set")?;
+            PathBuf::from(home).join(".local/share")
+        },
+    };
+    let dir = base.join(env!("CARGO_PKG_NAME"));
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    Ok(dir)
+}
+
+pub fn unix_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+pub fn unix_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+}
+
+/// UTC `(year, month, day)` of a Unix time, from Howard Hinnant's date algorithms.
+pub fn utc_date(secs: u64) -> (u64, u64, u64) {
+    let z = secs / 86_400 + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as u64, month, day)
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize,Serialize};
+
+use crate::heatmap::KeyStat;
+use crate::stats::{KeyKind,TypingStats};
+
+/// What part of Rust syntax a character of a line belongs to.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Serialize,Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    Lifetime,
+    Turbofish,
+    ClosurePipe,
+    Try,
+    Attribute,
+    PathSep,
+    Keyword,
+    Ident,
+    Literal,
+    Symbol,
+    Space,
+}
+
+pub const CATEGORIES: [Category; 11] = [
+    Category::Lifetime,
+    Category::Turbofish,
+    Category::ClosurePipe,
+    Category::Try,
+    Category::Attribute,
+    Category::PathSep,
+    Category::Keyword,
+    Category::Ident,
+    Category::Literal,
+    Category::Symbol,
+    Category::Space,
+];
+
+impl Category {
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Lifetime => "lifetimes",
+            Category::Turbofish => "turbofish",
+            Category::ClosurePipe => "closure pipes",
+            Category::Try => "? operator",
+            Category::Attribute => "attributes",
+            Category::PathSep => "path ::",
+            Category::Keyword => "keywords",
+            Category::Ident => "identifiers",
+            Category::Literal => "literals",
+            Category::Symbol => "other symbols",
+            Category::Space => "spaces",
+        }
+    }
+}
+
+const KEYWORDS: [&str; 36] = [
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match",
+    "mod", "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct",
+    "super", "trait", "true", "type", "unsafe", "use",
+];
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Index just past the bracket closing the one at `start`, or the end of the line.
+fn closing(chars: &[char], start: usize, open: char, close: char) -> usize {
+    let mut depth = 0;
+    for (i, &c) in chars.iter().enumerate().skip(start) {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return i + 1;
+            }
+        }
+    }
+    chars.len()
+}
+
+/// Whether a `|` at `i` opens a closure rather than being an or.
+fn opens_closure(chars: &[char], i: usize) -> bool {
+    let before: String = chars[..i].iter().collect();
+    let before = before.trim_end();
+    before.is_empty() || before.ends_with("move") || before.ends_with(['(', ',', '=', '{', '[', ';'])
+}
+
+/// Categorizes every character of `line`. Lines are single lines out of
+/// context, so this is a best guess rather than a real lexer.
+pub fn categorize(line: &str) -> Vec<Category> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut cats = Vec::with_capacity(chars.len());
+    let mut in_closure_params = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let (cat, end) = if c.is_whitespace() {
+            (Category::Space, i + 1)
+        } else if c == '#' && (next == Some('[') || (next == Some('!') && chars.get(i + 2) == Some(&'['))) {
+            let open = if next == Some('[') { i + 1 } else { i + 2 };
+            (Category::Attribute, closing(&chars, open, '[', ']'))
+        } else if c == ':' && next == Some(':') {
+            if chars.get(i + 2) == Some(&'<') {
+                (Category::Turbofish, closing(&chars, i + 2, '<', '>'))
+            } else {
+                (Category::PathSep, i + 2)
+            }
+        } else if c == '\'' && next.is_some_and(is_ident_start) {
+            let len = chars[i + 1..].iter().take_while(|&&c| is_ident(c)).count();
+            if chars.get(i + 1 + len) == Some(&'\'') {
+                (Category::Literal, i + len + 2)
+            } else {
+                (Category::Lifetime, i + 1 + len)
+            }
+        } else if c == '\'' || c == '"' {
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c {
+                end += if chars[end] == '\\' { 2 } else { 1 };
+            }
+            (Category::Literal, (end + 1).min(chars.len()))
+        } else if c.is_ascii_digit() {
+            let mut end = i + chars[i..].iter().take_while(|&&c| is_ident(c)).count();
+            // a fraction, but not a range or a method call
+            if chars.get(end) == Some(&'.') && chars.get(end + 1).is_some_and(|c| c.is_ascii_digit()) {
+                end += 1 + chars[end + 1..].iter().take_while(|&&c| is_ident(c)).count();
+            }
+            (Category::Literal, end)
+        } else if is_ident_start(c) {
+            let len = chars[i..].iter().take_while(|&&c| is_ident(c)).count();
+            let word: String = chars[i..i + len].iter().collect();
+            if KEYWORDS.contains(&word.as_str()) {
+                (Category::Keyword, i + len)
+            } else {
+                (Category::Ident, i + len)
+            }
+        } else if c == '?' {
+            (Category::Try, i + 1)
+        } else if c == '|' && in_closure_params {
+            in_closure_params = false;
+            (Category::ClosurePipe, i + 1)
+        } else if c == '|' && opens_closure(&chars, i) {
+            if next == Some('|') {
+                (Category::ClosurePipe, i + 2)
+            } else {
+                in_closure_params = true;
+                (Category::ClosurePipe, i + 1)
+            }
+        } else {
+            (Category::Symbol, i + 1)
+        };
+        cats.resize(end, cat);
+        i = end;
+    }
+    cats
+}
+
+/// Keystroke time and errors by the category of the character expected.
+pub fn token_stats(stats: &TypingStats) -> Vec<(Category, KeyStat)> {
+    let lines: Vec<_> = stats.shown.iter().map(|s| categorize(&s.line)).collect();
+    let mut by_cat = vec![KeyStat::default(); CATEGORIES.len()];
+    let mut prev = None;
+    for event in &stats.events {
+        let correct = match event.kind {
+            KeyKind::Char { expected: Some(_), correct, .. } => correct,
+            KeyKind::Refused { expected: Some(_), .. } => false,
+            _ => {
+                prev = Some(event);
+                continue;
+            },
+        };
+        let latency_ms = prev.filter(|p: &&_| p.line == event.line).map(|p| event.time_ms - p.time_ms);
+        if let Some(&cat) = lines.get(event.line).and_then(|cats| cats.get(event.pos)) {
+            by_cat[cat as usize].add(correct, latency_ms);
+        }
+        prev = Some(event);
+    }
+    CATEGORIES.iter().copied().zip(by_cat).filter(|(_, s)| s.count > 0).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The categories of `token`'s characters where it first appears in `line`.
+    fn cats(line: &str, token: &str) -> Vec<Category> {
+        let start = line[..line.find(token).unwrap()].chars().count();
+        categorize(line)[start..start + token.chars().count()].to_vec()
+    }
+
+    fn all(cat: Category, n: usize) -> Vec<Category> {
+        vec![cat; n]
+    }
+
+    #[test]
+    fn every_character_gets_a_category() {
+        let line = "pub fn f<'a>(x: &'a str) -> Option<&'a str> { Some(x) }";
+        assert_eq!(categorize(line).len(), line.chars().count());
+    }
+
+    #[test]
+    fn lifetimes_and_char_literals() {
+        assert_eq!(cats("fn f<'a>(x: &'a str)", "'a>"), [Category::Lifetime, Category::Lifetime, Category::Symbol]);
+        assert_eq!(cats("let c = 'a';", "'a'"), all(Category::Literal, 3));
+        assert_eq!(cats("let c = '\\n';", "'\\n'"), all(Category::Literal, 4));
+        assert_eq!(cats("let s: &'static str = x;", "'static"), all(Category::Lifetime, 7));
+    }
+
+    #[test]
+    fn or_is_not_a_closure() {
+        assert_eq!(cats("if a || b {", "||"), all(Category::Symbol, 2));
+        assert_eq!(cats("let x = a | b;", "|"), [Category::Symbol]);
+    }
+
+    #[test]
+    fn closure_pipes() {
+        assert_eq!(cats("v.iter().map(|x| x + 1)", "|x|"), [Category::ClosurePipe, Category::Ident, Category::ClosurePipe]);
+        assert_eq!(cats("thread::spawn(move || run())", "||"), all(Category::ClosurePipe, 2));
+        // the or inside the closure body is not a pipe
+        assert_eq!(cats("f(|a, b| a || b)", "| a"), [Category::ClosurePipe, Category::Space, Category::Ident]);
+        assert_eq!(cats("f(|a, b| a || b)", "||"), all(Category::Symbol, 2));
+    }
+
+    #[test]
+    fn turbofish_nests() {
+        let line = "let v = x.collect::<Vec<Option<u8>>>();";
+        assert_eq!(cats(line, "::<Vec<Option<u8>>>"), all(Category::Turbofish, 19));
+        assert_eq!(cats(line, "();"), all(Category::Symbol, 3));
+    }
+
+    #[test]
+    fn paths_attributes_and_try() {
+        assert_eq!(cats("use std::io;", "::"), all(Category::PathSep, 2));
+        assert_eq!(cats("#[derive(Debug)]", "#[derive(Debug)]"), all(Category::Attribute, 16));
+        assert_eq!(cats("#![allow(dead_code)] x", " x"), [Category::Space, Category::Ident]);
+        assert_eq!(cats("let f = File::open(p)?;", "?"), [Category::Try]);
+    }
+
+    #[test]
+    fn numbers_stop_at_ranges_and_methods() {
+        assert_eq!(cats("for i in 0..10 {", "0..10"), [Category::Literal, Category::Symbol, Category::Symbol, Category::Literal, Category::Literal]);
+        assert_eq!(cats("let x = 1.5f32;", "1.5f32"), all(Category::Literal, 6));
+        assert_eq!(cats("let y = 2.pow(3);", "2."), [Category::Literal, Category::Symbol]);
+    }
+}
@@ -6,9 +6,10 @@ use anyhow::Result;
 
 use crossterm::{
     execute,queue,
-    event::{DisableMouseCapture,EnableMouseCapture},
-    terminal::{SetSize,Clear,ClearType,enable_raw_mode,disable_raw_mode},
-    cursor::{MoveLeft,MoveDown,MoveTo,Hide,Show,MoveToColumn},
+    event::{DisableFocusChange,DisableMouseCapture,EnableFocusChange,EnableMouseCapture},
+    event::{KeyboardEnhancementFlags,PopKeyboardEnhancementFlags,PushKeyboardEnhancementFlags},
+    terminal::{SetSize,Clear,ClearType,enable_raw_mode,disable_raw_mode,supports_keyboard_enhancement},
+    cursor::{MoveLeft,MoveDown,MoveTo,Hide,Show,MoveToColumn,SavePosition,RestorePosition},
     style::{ResetColor,SetForegroundColor,SetBackgroundColor,Color,Attribute}
 };
 
@@ -16,14 +17,29 @@ use futures_timer::Delay;
 
 use scopeguard::{guard,ScopeGuard};
 
-use crate::game::TypingStats;
+use crate::heatmap::{Heatmap,KeyStat,KEYBOARD,LEVELS};
+use crate::race::Racer;
+use crate::stats::{SkipReason,TypingStats,HESITATION_MS};
+use crate::tokens::{token_stats,Category};
 
-pub fn setup_tui() -> Result<ScopeGuard<(),impl FnOnce(()) -> ()>> {
+pub fn setup_tui() -> Result<ScopeGuard<(),impl FnOnce(())>> {
     enable_raw_mode()?;
-    execute!(io::stdout(), EnableMouseCapture)?;
+    execute!(io::stdout(), EnableMouseCapture, EnableFocusChange)?;
+    // Terminals that speak the kitty keyboard protocol then report
+    // Ctrl+Backspace as itself rather than as Ctrl+H. A terminal that doesn't
+    // answer the query is taken not to.
+    let enhanced = supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced {
+        execute!(io::stdout(), PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES))?;
+    }
 
-    let guard = guard((), |_| {
-        match execute!(io::stdout(), Show, DisableMouseCapture) {
+    let guard = guard((), move |_| {
+        if enhanced {
+            if let Err(e) = execute!(io::stdout(), PopKeyboardEnhancementFlags) {
+                eprintln!("Error in scopeguard: {:#?}",e);
+            }
+        }
+        match execute!(io::stdout(), Show, DisableMouseCapture, DisableFocusChange) {
             Ok(_) => (),
             Err(e) => {
                 eprintln!("Error in scopeguard: {:#?}",e);
@@ -45,13 +61,14 @@ async fn delay(millis: u64) {
 
 pub fn print_centered(cols: u16, line: &str) -> Result<u16> {
     let line_len = u16::try_from(line.len())?;
-    let offset: u16 = (cols - line_len)/2;
+    let offset: u16 = cols.saturating_sub(line_len)/2;
     queue!(io::stdout(),Clear(ClearType::CurrentLine),MoveToColumn(offset))?;
     write!(io::stdout(),"{}",line)?;
     Ok(offset)
 }
 
-pub async fn show_intro() -> Result<u16> {
+/// Grows the terminal to the minimum size and clears it, returning the width.
+fn clear_screen() -> Result<u16> {
     let (mut cols,mut rows) = crossterm::terminal::size()?;
     if cols < 80 {
         cols = 80;
@@ -60,49 +77,74 @@ pub async fn show_intro() -> Result<u16> {
         rows = 10;
     }
     execute!(io::stdout(),SetSize(cols,rows))?;
-
     queue!(io::stdout(),Clear(ClearType::All),Hide,MoveTo(0,0))?;
-    print_centered(cols,"--- WELCOME TO RUSTIC TYPSTER ---".into())?;
+    Ok(cols)
+}
+
+pub async fn show_intro() -> Result<u16> {
+    let cols = clear_screen()?;
+    print_centered(cols,"--- WELCOME TO RUSTIC TYPSTER ---")?;
     queue!(io::stdout(),MoveTo(0,1))?;
-    print_centered(cols,"The typing practice game for Rust".into())?;
+    print_centered(cols,"The typing practice game for Rust")?;
     io::stdout().flush()?;
 
     delay(1000).await;
     queue!(io::stdout(),MoveTo(0,3))?;
-    print_centered(cols,"Get ready to type!".into())?;
+    print_centered(cols,"Get ready to type!")?;
     io::stdout().flush()?;
 
     delay(500).await;
     queue!(io::stdout(),MoveTo(0,6))?;
-    print_centered(cols-16,"3".into())?;
+    print_centered(cols-16,"3")?;
     io::stdout().flush()?;
 
     delay(500).await;
-    print_centered(cols,"2".into())?;
+    print_centered(cols,"2")?;
     io::stdout().flush()?;
 
     delay(500).await;
-    print_centered(cols+16,"1".into())?;
+    print_centered(cols+16,"1")?;
     io::stdout().flush()?;
 
     delay(500).await;
     Ok(cols)
 }
 
+pub fn show_replay_intro(label: &str) -> Result<u16> {
+    let cols = clear_screen()?;
+    print_centered(cols,&format!("--- REPLAY OF A {} SESSION ---", label.to_uppercase()))?;
+    queue!(io::stdout(),MoveTo(0,1))?;
+    print_centered(cols,"Space pauses, Left/Right seek 5s, Home restarts, +/- change speed, Esc quits")?;
+    io::stdout().flush()?;
+    Ok(cols)
+}
+
+/// Clears the line rows before a replay redraws them.
+pub fn clear_replay_line() -> Result<()> {
+    for row in 5..=7 {
+        queue!(io::stdout(),MoveTo(0,row),Clear(ClearType::CurrentLine))?;
+    }
+    io::stdout().flush()?;
+    Ok(())
+}
+
 pub fn clear_countdown() -> Result<()> {
     queue!(io::stdout(),MoveTo(0,6),Clear(ClearType::CurrentLine))?;
     io::stdout().flush()?;
     Ok(())
 }
 
-pub fn display_current_line(cols: u16, line: &str, source: &str) -> Result<()> {
-    queue!(io::stdout(),MoveTo(0,5),Clear(ClearType::FromCursorDown))?;
+/// Shows `line` after `indent` columns of indentation, returning the column
+/// typing starts at.
+pub fn display_current_line(cols: u16, line: &str, source: &str, indent: usize) -> Result<u16> {
+    queue!(io::stdout(),MoveTo(0,5))?;
     print_centered(cols,&format!("FROM: {}",source))?;
-    queue!(io::stdout(),MoveTo(0,6),Clear(ClearType::FromCursorDown))?;
-    let offset = print_centered(cols,line)?;
-    queue!(io::stdout(),MoveTo(0,7),MoveToColumn(offset))?;
+    queue!(io::stdout(),MoveTo(0,6))?;
+    let offset = print_centered(cols,&format!("{:indent$}{}","",line))? + u16::try_from(indent)?;
+    // leave the stats rows below alone, they describe the previous line
+    queue!(io::stdout(),MoveTo(0,7),Clear(ClearType::CurrentLine),MoveToColumn(offset))?;
     io::stdout().flush()?;
-    Ok(())
+    Ok(offset)
 }
 
 pub fn show_cursor() -> Result<()> {
@@ -137,21 +179,289 @@ pub fn type_char(c: char, correct: bool) -> Result<()> {
     Ok(())
 }
 
-pub fn show_time(cols: u16, elapsed_time: u32) -> Result<()> {
-    queue!(io::stdout(),Hide,MoveTo(0,5))?;
-    print_centered(cols,&format!("t = {}ms",elapsed_time))?;
+/// Shows the closers inserted by the editor emulation right of the cursor.
+pub fn show_pending(pending: &str) -> Result<()> {
+    queue!(io::stdout(),SavePosition,SetForegroundColor(Color::DarkGrey))?;
+    // the trailing space clears a closer that was just typed over
+    write!(io::stdout(),"{} ",pending)?;
+    queue!(io::stdout(),ResetColor,RestorePosition)?;
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Shows the countdown or session progress above the current line.
+pub fn show_status(cols: u16, status: &str) -> Result<()> {
+    queue!(io::stdout(),SavePosition,MoveTo(0,3))?;
+    print_centered(cols,status)?;
+    queue!(io::stdout(),RestorePosition)?;
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Draws the ghost's cursor at `ghost` on the row below the typed line, and
+/// how far the player at `player` is ahead or behind on the row above.
+pub fn show_ghost(cols: u16, offset: u16, line: &[char], ghost: usize, player: usize) -> Result<()> {
+    let typed: String = line.iter().take(ghost).collect();
+    let cursor = line.get(ghost).copied().unwrap_or(' ');
+    queue!(io::stdout(),SavePosition,MoveTo(0,8),Clear(ClearType::CurrentLine),MoveToColumn(offset),SetForegroundColor(Color::DarkGrey))?;
+    write!(io::stdout(),"{}",typed)?;
+    queue!(io::stdout(),SetBackgroundColor(Color::DarkGrey),SetForegroundColor(Color::Black))?;
+    write!(io::stdout(),"{}",cursor)?;
+    queue!(io::stdout(),ResetColor,MoveTo(0,4))?;
+    let lead = if player > ghost {
+        format!("{} chars ahead of your ghost", player - ghost)
+    } else if player < ghost {
+        format!("{} chars behind your ghost", ghost - player)
+    } else {
+        "level with your ghost".to_owned()
+    };
+    print_centered(cols,&lead)?;
+    queue!(io::stdout(),RestorePosition)?;
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Shows how a finished line compared to the ghost's time.
+pub fn show_ghost_result(cols: u16, time_ms: u32, ghost_ms: u32) -> Result<()> {
+    queue!(io::stdout(),SavePosition,MoveTo(0,8),Clear(ClearType::CurrentLine),MoveTo(0,4))?;
+    if time_ms < ghost_ms {
+        print_centered(cols,&format!("beat your ghost by {}ms", ghost_ms - time_ms))?;
+    } else {
+        print_centered(cols,&format!("your ghost was {}ms faster", time_ms - ghost_ms))?;
+    }
+    queue!(io::stdout(),RestorePosition)?;
+    io::stdout().flush()?;
+    Ok(())
+}
+
+pub fn clear_ghost() -> Result<()> {
+    queue!(io::stdout(),SavePosition,MoveTo(0,4),Clear(ClearType::CurrentLine),MoveTo(0,8),Clear(ClearType::CurrentLine),RestorePosition)?;
+    io::stdout().flush()?;
+    Ok(())
+}
+
+/// Shows every racer's progress through the race below the line stats.
+pub fn show_racers(cols: u16, racers: &[Racer], total: usize) -> Result<()> {
+    const BAR: usize = 30;
+    queue!(io::stdout(),SavePosition)?;
+    for (i, racer) in racers.iter().enumerate() {
+        let filled = (racer.chars * BAR).checked_div(total).unwrap_or(0).min(BAR);
+        queue!(io::stdout(),MoveTo(0,12 + i as u16))?;
+        print_centered(cols,&format!(
+            "{:>12.12} [{}{}] {:>3.0} wpm{}",
+            racer.name, "#".repeat(filled), ".".repeat(BAR - filled), racer.wpm, if racer.done { ", done" } else { "" },
+        ))?;
+    }
+    queue!(io::stdout(),RestorePosition)?;
+    io::stdout().flush()?;
+    Ok(())
+}
+
+pub fn show_paused(cols: u16) -> Result<()> {
+    queue!(io::stdout(),Hide)?;
+    show_status(cols,"--- PAUSED: press any key to resume ---")
+}
+
+pub fn show_line_stats(cols: u16, stats: &TypingStats) -> Result<()> {
+    if let Some(line) = stats.lines.last() {
+        queue!(io::stdout(),Hide,MoveTo(0,9))?;
+        print_centered(cols,&format!("last line: t = {}ms, {:.0} wpm, {} errors",line.time_ms,line.wpm(),line.keys.errors()))?;
+        queue!(io::stdout(),MoveTo(0,10))?;
+        print_centered(cols,&format!("session: {:.0} wpm gross, {:.0} wpm net, {:.0} chars/min",stats.gross_wpm(),stats.net_wpm(),stats.cpm()))?;
+        io::stdout().flush()?;
+    }
+    Ok(())
+}
+
+/// Backgrounds for the heat levels, green to red.
+const HEAT: [u8; LEVELS] = [28, 70, 142, 172, 160];
+
+/// Writes `text` on the colour of `level`, greyed out for keys never typed.
+fn heat_cell(text: &str, level: Option<usize>) -> Result<()> {
+    match level {
+        Some(level) => queue!(io::stdout(),SetForegroundColor(Color::Black),SetBackgroundColor(Color::AnsiValue(HEAT[level])))?,
+        None => queue!(io::stdout(),SetForegroundColor(Color::DarkGrey))?,
+    }
+    write!(io::stdout(),"{}",text)?;
+    queue!(io::stdout(),ResetColor)?;
+    Ok(())
+}
+
+/// Draws one keyboard with each key coloured by `level`, from `(x, y)`.
+fn show_keyboard(x: u16, y: u16, title: &str, level: impl Fn(char) -> Option<usize>) -> Result<()> {
+    queue!(io::stdout(),MoveTo(x,y))?;
+    write!(io::stdout(),"{}",title)?;
+    for (i, keys) in KEYBOARD.iter().enumerate() {
+        queue!(io::stdout(),MoveTo(x + i as u16,y + 1 + i as u16))?;
+        for key in keys.chars() {
+            heat_cell(&key.to_string(),level(key))?;
+            write!(io::stdout()," ")?;
+        }
+    }
+    queue!(io::stdout(),MoveTo(x + 8,y + 5))?;
+    heat_cell(&format!("{:^13}","space"),level(' '))
+}
+
+fn show_bigrams(label: &str, bigrams: Vec<String>) -> Result<()> {
+    if bigrams.is_empty() {
+        write!(io::stdout(),"{}: none",label)?;
+    } else {
+        write!(io::stdout(),"{}: {}",label,bigrams.join(", "))?;
+    }
+    Ok(())
+}
+
+/// Rows taken by `show_heatmap`, including the blank one after it.
+const HEATMAP_ROWS: u16 = 12;
+
+/// Keyboards coloured by latency and error rate per key, then the worst
+/// bigrams. Returns the next free row.
+fn show_heatmap(cols: u16, row: u16, heatmap: &Heatmap) -> Result<u16> {
+    const WIDTH: u16 = 66;
+    let x = cols.saturating_sub(WIDTH)/2;
+    show_keyboard(x,row,"Latency per key",|key| heatmap.latency_level(key))?;
+    show_keyboard(x + 36,row,"Errors per key",|key| heatmap.error_level(key))?;
+    queue!(io::stdout(),MoveTo(x + 22,row + 7))?;
+    write!(io::stdout(),"fast, accurate ")?;
+    for level in 0..LEVELS {
+        heat_cell("  ",Some(level))?;
+    }
+    write!(io::stdout()," slow, error-prone")?;
+
+    let visible = |bigram: &str| bigram.replace(' ', "\u{2423}");
+    queue!(io::stdout(),MoveTo(x,row + 9))?;
+    show_bigrams("Slowest bigrams",heatmap.slowest_bigrams(5).iter()
+        .map(|(b, s)| format!("`{}` {}ms",visible(b),s.latency_ms().unwrap_or(0)))
+        .collect())?;
+    queue!(io::stdout(),MoveTo(x,row + 10))?;
+    show_bigrams("Most errors",heatmap.error_bigrams(5).iter()
+        .map(|(b, s)| format!("`{}` {}/{}",visible(b),s.errors,s.count))
+        .collect())?;
+    Ok(row + HEATMAP_ROWS)
+}
+
+/// Rows taken by `show_token_stats` for `tokens` categories.
+fn token_rows(tokens: usize) -> u16 {
+    2 + tokens.div_ceil(2) as u16
+}
+
+/// Time per key and error rate for each kind of token, slowest first, in two columns.
+fn show_token_stats(cols: u16, row: u16, tokens: &[(Category, KeyStat)]) -> Result<u16> {
+    const WIDTH: u16 = 76;
+    let x = cols.saturating_sub(WIDTH)/2;
+    let mut tokens = tokens.to_vec();
+    tokens.sort_by_key(|(_, s)| std::cmp::Reverse(s.latency_ms()));
+    queue!(io::stdout(),MoveTo(0,row))?;
+    print_centered(cols,"By token: time per key, errors")?;
+    let rows = tokens.len().div_ceil(2) as u16;
+    for (i, (cat, stat)) in tokens.iter().enumerate() {
+        let (col, line) = ((i as u16 / rows.max(1)) * 40, i as u16 % rows.max(1));
+        queue!(io::stdout(),MoveTo(x + col,row + 1 + line))?;
+        write!(io::stdout(),"{:>13} {:>4}ms {:>4.1}% err {:>3} keys",cat.name(),stat.latency_ms().unwrap_or(0),stat.error_rate() * 100.0,stat.count)?;
+    }
+    Ok(row + token_rows(tokens.len()))
+}
+
+/// Draws the heatmap and token table from `row`, leaving out whichever
+/// doesn't fit above the bottom of the terminal. Returns the next free row.
+fn show_breakdown(cols: u16, mut row: u16, stats: &TypingStats) -> Result<u16> {
+    let (_, rows) = crossterm::terminal::size()?;
+    if row + HEATMAP_ROWS <= rows {
+        row = show_heatmap(cols,row,&Heatmap::new(&stats.events))?;
+    }
+    let tokens = token_stats(stats);
+    if row + token_rows(tokens.len()) <= rows {
+        row = show_token_stats(cols,row,&tokens)?;
+    }
+    Ok(row)
+}
+
+/// The per key and per token breakdown on a page of its own, for when it
+/// didn't fit below the results.
+pub fn show_breakdown_page(cols: u16, stats: &TypingStats) -> Result<()> {
+    queue!(io::stdout(),Clear(ClearType::All),MoveTo(0,0))?;
+    let row = show_breakdown(cols,0,stats)?;
+    queue!(io::stdout(),MoveTo(0,row.saturating_sub(1)),MoveDown(1),MoveToColumn(0))?;
     io::stdout().flush()?;
     Ok(())
 }
 
-pub fn show_results(cols: u16, stats: TypingStats) -> Result<()> {
+/// Shows the session results, returning whether the breakdown was left out
+/// for lack of rows and is waiting for `show_breakdown_page`.
+pub fn show_results(cols: u16, label: &str, stats: &TypingStats) -> Result<bool> {
+    queue!(io::stdout(),MoveTo(0,3))?;
+    print_centered(cols,&format!("--- {} session ---", label))?;
+    queue!(io::stdout(),MoveTo(0,5),Clear(ClearType::FromCursorDown))?;
     if stats.total_chars > 0 {
-        let accuracy = 100.0-((stats.total_mistakes as f32)*100.0/(stats.total_chars as f32));
-        let char_per_min = stats.total_chars*60*1000/stats.total_time_ms;
-        queue!(io::stdout(),MoveTo(0,6),Clear(ClearType::FromCursorDown))?;
-        print_centered(cols,&format!("You typed {} chars/min at {:.2}% accuracy", char_per_min, accuracy))?;
+        print_centered(cols,&format!("You typed {:.0} chars/min at {:.2}% accuracy", stats.cpm(), stats.accuracy()))?;
+        queue!(io::stdout(),MoveTo(0,7))?;
+        let mut speed = format!("{:.1} wpm gross, {:.1} wpm net over {} lines", stats.gross_wpm(), stats.net_wpm(), stats.lines.len());
+        if let Some(burst) = stats.burst_wpm() {
+            speed += &format!(", {:.0} wpm burst", burst);
+        }
+        if let Some(consistency) = stats.consistency() {
+            speed += &format!(", {:.0}% consistency", consistency);
+        }
+        print_centered(cols,&speed)?;
+        queue!(io::stdout(),MoveTo(0,8))?;
+        let mut keys = format!(
+            "{} keystrokes: {} correct, {} corrected, {} uncorrected, {} extra",
+            stats.keys.total, stats.keys.correct, stats.keys.corrected_errors, stats.keys.uncorrected_errors, stats.keys.extra_chars,
+        );
+        if stats.keys.refused > 0 {
+            keys += &format!(", {} refused", stats.keys.refused);
+        }
+        print_centered(cols,&keys)?;
+        if !stats.skipped.is_empty() {
+            let rerolled: Vec<_> = stats.skipped.iter()
+                .filter(|s| s.reason == SkipReason::Rerolled)
+                .map(|s| s.source.as_str())
+                .collect();
+            queue!(io::stdout(),MoveTo(0,9))?;
+            if rerolled.is_empty() {
+                print_centered(cols,&format!("{} lines skipped", stats.skipped.len()))?;
+            } else {
+                print_centered(cols,&format!("{} lines skipped, rerolled away from {}", stats.skipped.len(), rerolled.join(", ")))?;
+            }
+        }
+        if let Some(reaction) = stats.reaction_ms() {
+            queue!(io::stdout(),MoveTo(0,10))?;
+            print_centered(cols,&format!(
+                "{}ms to the first keystroke of a line, {} hesitations of {}s or more",
+                reaction, stats.hesitations(), HESITATION_MS / 1000,
+            ))?;
+        }
+        let mut row = 12;
+        for (label, line) in [("Best", stats.best_line()), ("Worst", stats.worst_line())] {
+            if let Some(line) = line {
+                queue!(io::stdout(),MoveTo(0,row))?;
+                print_centered(cols,&format!("{} line: {:.1} wpm, {} errors", label, line.wpm(), line.keys.errors()))?;
+                queue!(io::stdout(),MoveTo(0,row+1))?;
+                print_centered(cols,&line.line)?;
+                row += 3;
+            }
+        }
+        if let Some(skipped) = stats.skipped.last() {
+            queue!(io::stdout(),MoveTo(0,row))?;
+            print_centered(cols,"Last skipped line:")?;
+            queue!(io::stdout(),MoveTo(0,row+1))?;
+            print_centered(cols,&skipped.line)?;
+            row += 3;
+        }
+        let (_, rows) = crossterm::terminal::size()?;
+        let needed = HEATMAP_ROWS + token_rows(token_stats(stats).len());
+        if row + needed <= rows {
+            let row = show_breakdown(cols,row,stats)?;
+            queue!(io::stdout(),MoveTo(0,row))?;
+        } else {
+            queue!(io::stdout(),MoveTo(0,row.min(rows.saturating_sub(1))))?;
+            print_centered(cols,"Press any key for the per key and per token breakdown, Esc to finish")?;
+            queue!(io::stdout(),MoveDown(1),MoveToColumn(0))?;
+            io::stdout().flush()?;
+            return Ok(true);
+        }
     }
     queue!(io::stdout(),MoveDown(1),MoveToColumn(0))?;
     io::stdout().flush()?;
-    Ok(())
+    Ok(false)
 }